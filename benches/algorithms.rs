@@ -0,0 +1,87 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use path_finding::algorithms::{create_algorithm, AlgorithmType};
+use path_finding::astar::Heuristic;
+use path_finding::cellular_automata::CellularAutomata;
+use path_finding::grid::Grid;
+use path_finding::map_generator::MapGenerator;
+use path_finding::maze::Maze;
+
+const SIZES: [usize; 3] = [50, 256, 1024];
+/// Caves stop at 256, see "Search Timings" in the README
+const CAVE_SIZES: [usize; 3] = [50, 128, 256];
+
+/// Grid along with the start and end to search between
+type Map = (Grid, (usize, usize), (usize, usize));
+
+fn open(size: usize) -> Map {
+    (Grid::new(size, size), (0, 0), (size - 1, size - 1))
+}
+
+fn cave(size: usize) -> Map {
+    let mut grid = Grid::new(size, size);
+    CellularAutomata {
+        seed: 7,
+        ..Default::default()
+    }
+    .generate(&mut grid);
+    let [start, end] = grid.endpoints().expect("cave has a start and an end");
+    (grid, start, end)
+}
+
+/// Recursive backtracker maze, corner to corner
+fn maze(size: usize) -> Map {
+    let mut grid = Grid::new(size, size);
+    Maze {
+        seed: 7,
+        ..Default::default()
+    }
+    .generate(&mut grid);
+    let [start, end] = grid.endpoints().expect("maze has a start and an end");
+    (grid, start, end)
+}
+
+fn run_to_end(
+    algorithm_type: AlgorithmType,
+    grid: &Grid,
+    start: (usize, usize),
+    end: (usize, usize),
+) -> bool {
+    let mut algorithm =
+        create_algorithm(algorithm_type, grid, start, end, &Heuristic::Manhattan, 1.0);
+    while algorithm.step(grid) {}
+    algorithm.found_path()
+}
+
+fn bench_maps(c: &mut Criterion, name: &str, sizes: &[usize], map: fn(usize) -> Map) {
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+
+    for &size in sizes {
+        let (grid, start, end) = map(size);
+        let supported = AlgorithmType::all().iter().filter(|a| a.supports(&grid));
+        for &algorithm_type in supported {
+            group.bench_with_input(
+                BenchmarkId::new(algorithm_type.name(), format!("{size}x{size}")),
+                &grid,
+                |b, grid| b.iter(|| run_to_end(algorithm_type, grid, start, end)),
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_open(c: &mut Criterion) {
+    bench_maps(c, "open", &SIZES, open);
+}
+
+fn bench_caves(c: &mut Criterion) {
+    bench_maps(c, "cave", &CAVE_SIZES, cave);
+}
+
+fn bench_mazes(c: &mut Criterion) {
+    bench_maps(c, "maze", &SIZES, maze);
+}
+
+criterion_group!(benches, bench_open, bench_caves, bench_mazes);
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use path_finding::cellular_automata::CellularAutomata;
+use path_finding::grid::Grid;
+use path_finding::map_generator::MapGenerator;
+use path_finding::maze::{Maze, MazeAlgorithm};
+
+/// Caves stop at 256, see "Search Timings" in the README
+const SIZES: [usize; 3] = [50, 128, 256];
+
+fn bench_generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cellular_automata");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let generator = CellularAutomata {
+            seed: 7,
+            ..Default::default()
+        };
+        group.bench_function(
+            BenchmarkId::new("generate", format!("{size}x{size}")),
+            |b| {
+                b.iter(|| {
+                    let mut grid = Grid::new(size, size);
+                    generator.generate(&mut grid);
+                    grid
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_mazes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("maze");
+    group.sample_size(10);
+
+    for size in [50, 256, 1024] {
+        for &algorithm in MazeAlgorithm::all() {
+            let generator = Maze {
+                algorithm,
+                seed: 7,
+                ..Default::default()
+            };
+            group.bench_function(
+                BenchmarkId::new(algorithm.name(), format!("{size}x{size}")),
+                |b| {
+                    b.iter(|| {
+                        let mut grid = Grid::new(size, size);
+                        generator.generate(&mut grid);
+                        grid
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_generate, bench_mazes);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+use path_finding::algorithms::{create_algorithm, AlgorithmType};
+use path_finding::astar::Heuristic;
+use path_finding::grid::{Cell, Grid};
+use rand::prelude::*;
+use rand::rngs::SmallRng;
+use std::time::Instant;
+
+/// Times each algorithm from corner to corner on large maps:
+///
+/// ```text
+/// cargo run --release --example bench_search -- 2000
+/// ```
+fn main() {
+    let size: usize = std::env::args()
+        .nth(1)
+        .map_or(1000, |arg| arg.parse().expect("size must be a number"));
+
+    let open = Grid::new(size, size);
+    let mut noise = Grid::new(size, size);
+    let mut rng = SmallRng::seed_from_u64(1);
+    for y in 0..size {
+        for x in 0..size {
+            if rng.gen::<f32>() < 0.25 {
+                noise.set(x, y, Cell::Wall);
+            }
+        }
+    }
+
+    let start = (0, 0);
+    let end = (size - 1, size - 1);
+    noise.set(start.0, start.1, Cell::Empty);
+    noise.set(end.0, end.1, Cell::Empty);
+
+    let algorithms = [
+        AlgorithmType::Dijkstra,
+        AlgorithmType::AStar,
+        AlgorithmType::Bfs,
+        AlgorithmType::Dfs,
+        AlgorithmType::Jps,
+        AlgorithmType::BiAStar,
+    ];
+
+    for (map, grid) in [("open", &open), ("noise", &noise)] {
+        for &algorithm_type in &algorithms {
+            let timer = Instant::now();
+            let mut algorithm =
+                create_algorithm(algorithm_type, grid, start, end, &Heuristic::Manhattan, 1.0);
+            while algorithm.step(grid) {}
+            let elapsed = timer.elapsed();
+
+            println!(
+                "{size}x{size} {map:<6} {:<12} {:>10.1} ms  expanded {:>9}  found {}",
+                algorithm_type.name(),
+                elapsed.as_secs_f64() * 1000.0,
+                algorithm.stats().nodes_expanded,
+                algorithm.found_path()
+            );
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use path_finding::algorithms::AlgorithmType;
+use path_finding::movingai::{parse_map, parse_scenarios, run_scenarios};
+use std::{env, fs, process};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <file.map> <file.scen>", args[0]);
+        process::exit(2);
+    }
+
+    let read = |path: &str| {
+        fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("{path}: {err}");
+            process::exit(1);
+        })
+    };
+    let grid = parse_map(&read(&args[1])).unwrap_or_else(|err| {
+        eprintln!("{}: {err}", args[1]);
+        process::exit(1);
+    });
+    let scenarios = parse_scenarios(&read(&args[2])).unwrap_or_else(|err| {
+        eprintln!("{}: {err}", args[2]);
+        process::exit(1);
+    });
+
+    let results = run_scenarios(&grid, &scenarios, AlgorithmType::all());
+    for algorithm in AlgorithmType::all() {
+        let mut optimal = 0;
+        for result in results.iter().filter(|r| r.algorithm == *algorithm) {
+            if result.is_optimal() {
+                optimal += 1;
+            } else {
+                let scenario = &scenarios[result.scenario];
+                println!(
+                    "{}: scenario {} {:?} -> {:?}: length {:?}, optimal {:.8}",
+                    algorithm.name(),
+                    result.scenario,
+                    scenario.start,
+                    scenario.end,
+                    result.length,
+                    result.optimal_length
+                );
+            }
+        }
+        println!(
+            "{}: {}/{} optimal",
+            algorithm.name(),
+            optimal,
+            scenarios.len()
+        );
+    }
+}
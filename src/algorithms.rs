@@ -0,0 +1,510 @@
+use crate::astar::{AStar, Heuristic};
+use crate::bfs::Bfs;
+use crate::bidirectional::{Bidirectional, BidirectionalMode};
+use crate::dfs::Dfs;
+use crate::dijkstra::Dijkstra;
+use crate::dstar_lite::DStarLite;
+use crate::grid::{Grid, Neighborhood};
+use crate::jps::JumpPointSearch;
+use crate::pathfinding::{PathError, PathResult, PathfindingAlgorithm};
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum AlgorithmType {
+    Dijkstra,
+    #[default]
+    AStar,
+    Bfs,
+    Dfs,
+    Jps,
+    BiBfs,
+    BiDijkstra,
+    BiAStar,
+    DStarLite,
+}
+
+impl AlgorithmType {
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::Dijkstra,
+            Self::AStar,
+            Self::Bfs,
+            Self::Dfs,
+            Self::Jps,
+            Self::BiBfs,
+            Self::BiDijkstra,
+            Self::BiAStar,
+            Self::DStarLite,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Dijkstra => "Dijkstra",
+            Self::AStar => "A*",
+            Self::Bfs => "BFS",
+            Self::Dfs => "DFS",
+            Self::Jps => "JPS",
+            Self::BiBfs => "Bi-BFS",
+            Self::BiDijkstra => "Bi-Dijkstra",
+            Self::BiAStar => "Bi-A*",
+            Self::DStarLite => "D* Lite",
+        }
+    }
+
+    /// Whether the algorithm finds valid paths on `grid`. JPS only handles
+    /// 8-way moves without corner cutting over terrain that costs the same
+    /// everywhere
+    pub fn supports(&self, grid: &Grid) -> bool {
+        match self {
+            Self::Jps => {
+                grid.neighborhood == Neighborhood::EightNoCornerCutting && grid.has_uniform_costs()
+            }
+            _ => true,
+        }
+    }
+
+    /// The next algorithm in `all` that supports `grid`
+    pub fn next_on(&self, grid: &Grid) -> Self {
+        let mut next = self.next();
+        while !next.supports(grid) {
+            next = next.next();
+        }
+        next
+    }
+
+    pub fn next(&self) -> Self {
+        let all = Self::all();
+        let current_idx = all.iter().position(|&a| a == *self).unwrap_or(0);
+        let next_idx = (current_idx + 1) % all.len();
+        all[next_idx]
+    }
+}
+
+/// `heuristic` is used by the A* family, `weight` only by A*
+pub fn create_algorithm(
+    algorithm_type: AlgorithmType,
+    grid: &Grid,
+    start: (usize, usize),
+    end: (usize, usize),
+    heuristic: &Heuristic,
+    weight: f32,
+) -> Box<dyn PathfindingAlgorithm> {
+    create_algorithm_to_nearest(algorithm_type, grid, start, &[end], heuristic, weight)
+}
+
+/// `create_algorithm` for a search that stops at the first of `goals` it
+/// reaches, which is the cheapest one for all but BFS, DFS and Bi-BFS
+pub fn create_algorithm_to_nearest(
+    algorithm_type: AlgorithmType,
+    grid: &Grid,
+    start: (usize, usize),
+    goals: &[(usize, usize)],
+    heuristic: &Heuristic,
+    weight: f32,
+) -> Box<dyn PathfindingAlgorithm> {
+    let heuristic = heuristic.clone();
+    match algorithm_type {
+        AlgorithmType::Dijkstra => Box::new(Dijkstra::to_nearest(grid, start, goals)),
+        AlgorithmType::AStar => {
+            Box::new(AStar::to_nearest(grid, start, goals, heuristic).with_weight(weight))
+        }
+        AlgorithmType::Bfs => Box::new(Bfs::to_nearest(grid, start, goals)),
+        AlgorithmType::Dfs => Box::new(Dfs::to_nearest(grid, start, goals)),
+        AlgorithmType::Jps => Box::new(JumpPointSearch::to_nearest(grid, start, goals)),
+        AlgorithmType::BiBfs => Box::new(Bidirectional::to_nearest(
+            grid,
+            start,
+            goals,
+            BidirectionalMode::Bfs,
+            heuristic,
+        )),
+        AlgorithmType::BiDijkstra => Box::new(Bidirectional::to_nearest(
+            grid,
+            start,
+            goals,
+            BidirectionalMode::Dijkstra,
+            heuristic,
+        )),
+        AlgorithmType::BiAStar => Box::new(Bidirectional::to_nearest(
+            grid,
+            start,
+            goals,
+            BidirectionalMode::AStar,
+            heuristic,
+        )),
+        AlgorithmType::DStarLite => Box::new(DStarLite::to_nearest(grid, start, goals, heuristic)),
+    }
+}
+
+/// `create_algorithm` for endpoints that passed `Grid::validate_endpoints`
+pub fn try_create_algorithm(
+    algorithm_type: AlgorithmType,
+    grid: &Grid,
+    start: (usize, usize),
+    end: (usize, usize),
+    heuristic: &Heuristic,
+    weight: f32,
+) -> Result<Box<dyn PathfindingAlgorithm>, PathError> {
+    try_create_algorithm_to_nearest(algorithm_type, grid, start, &[end], heuristic, weight)
+}
+
+/// `create_algorithm_to_nearest` once `start` and every goal passed
+/// `Grid::validate_endpoints` and the algorithm `supports` the grid, no goals
+/// at all is `PathError::NoEnd`
+pub fn try_create_algorithm_to_nearest(
+    algorithm_type: AlgorithmType,
+    grid: &Grid,
+    start: (usize, usize),
+    goals: &[(usize, usize)],
+    heuristic: &Heuristic,
+    weight: f32,
+) -> Result<Box<dyn PathfindingAlgorithm>, PathError> {
+    if goals.is_empty() {
+        return Err(PathError::NoEnd);
+    }
+    for &goal in goals {
+        grid.validate_endpoints(start, goal)?;
+    }
+    if !algorithm_type.supports(grid) {
+        return Err(PathError::UnsupportedGrid(algorithm_type.name()));
+    }
+    Ok(create_algorithm_to_nearest(
+        algorithm_type,
+        grid,
+        start,
+        goals,
+        heuristic,
+        weight,
+    ))
+}
+
+/// Settings for `find_path`
+#[derive(Clone)]
+pub struct SearchOptions {
+    /// Used by the A* family, None picks `Heuristic::for_neighborhood`
+    pub heuristic: Option<Heuristic>,
+    /// Used only by A*, see `AStar::with_weight`
+    pub weight: f32,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            heuristic: None,
+            weight: 1.0,
+        }
+    }
+}
+
+/// Runs a whole search from `start` to `end` on `grid`
+pub fn find_path(
+    grid: &Grid,
+    start: (usize, usize),
+    end: (usize, usize),
+    algorithm: AlgorithmType,
+    options: &SearchOptions,
+) -> Result<PathResult, PathError> {
+    find_path_to_nearest(grid, start, &[end], algorithm, options)
+}
+
+/// Runs a whole search from `start` to the first of `goals` it reaches, the
+/// path's last cell tells which one that is
+pub fn find_path_to_nearest(
+    grid: &Grid,
+    start: (usize, usize),
+    goals: &[(usize, usize)],
+    algorithm: AlgorithmType,
+    options: &SearchOptions,
+) -> Result<PathResult, PathError> {
+    let heuristic = options
+        .heuristic
+        .clone()
+        .unwrap_or_else(|| Heuristic::for_neighborhood(grid.neighborhood));
+    try_create_algorithm_to_nearest(algorithm, grid, start, goals, &heuristic, options.weight)?
+        .run_to_end(grid)
+        .ok_or(PathError::NoPath)
+}
+
+/// Paths from `start` to every one of `goals`, nearest goal first. Each
+/// search stops at the nearest goal not reached yet, goals that can't be
+/// reached at all are left out
+pub fn find_paths_to_all(
+    grid: &Grid,
+    start: (usize, usize),
+    goals: &[(usize, usize)],
+    algorithm: AlgorithmType,
+    options: &SearchOptions,
+) -> Result<Vec<PathResult>, PathError> {
+    let mut remaining = goals.to_vec();
+    let mut results = Vec::new();
+    while !remaining.is_empty() {
+        let result = match find_path_to_nearest(grid, start, &remaining, algorithm, options) {
+            Ok(result) => result,
+            Err(PathError::NoPath) => break,
+            Err(err) => return Err(err),
+        };
+        remaining.retain(|goal| result.path.last() != Some(goal));
+        results.push(result);
+    }
+    if results.is_empty() {
+        return Err(PathError::NoPath);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Cell, Direction, Terrain, Wrap, DIAGONAL_COST, STRAIGHT_COST};
+    use crate::hex::HexOrientation;
+
+    /// Whether every step of `path` is a move `grid` allows
+    fn is_valid_path(grid: &Grid, path: &[(usize, usize)]) -> bool {
+        path.windows(2)
+            .all(|pair| grid.neighbors(pair[0].0, pair[0].1).any(|n| n == pair[1]))
+    }
+
+    /// Algorithms that always find the cheapest path
+    fn is_optimal(algorithm: AlgorithmType) -> bool {
+        !matches!(
+            algorithm,
+            AlgorithmType::Bfs | AlgorithmType::Dfs | AlgorithmType::BiBfs
+        )
+    }
+
+    #[test]
+    fn test_find_path() {
+        let mut grid = Grid::new(4, 3);
+        grid.set(1, 0, Cell::Wall);
+        grid.set(1, 1, Cell::Wall);
+        let options = SearchOptions::default();
+
+        for &neighborhood in Neighborhood::all() {
+            grid.neighborhood = neighborhood;
+            let cheapest = find_path(&grid, (0, 0), (3, 0), AlgorithmType::Dijkstra, &options)
+                .map(|result| result.cost);
+
+            for algorithm in AlgorithmType::all().iter().filter(|a| a.supports(&grid)) {
+                let name = format!("{} on {}", algorithm.name(), neighborhood.name());
+                let result = find_path(&grid, (0, 0), (3, 0), *algorithm, &options);
+                let Ok(result) = result else {
+                    assert_eq!(result.map(|r| r.cost), cheapest, "{name}");
+                    continue;
+                };
+                assert_eq!(result.path.first(), Some(&(0, 0)), "{name}");
+                assert_eq!(result.path.last(), Some(&(3, 0)), "{name}");
+                assert!(is_valid_path(&grid, &result.path), "{name}: invalid move");
+                assert_eq!(result.cost, grid.path_cost(&result.path), "{name}");
+                assert_eq!(result.stats.path_cost, Some(result.cost), "{name}");
+                if is_optimal(*algorithm) {
+                    assert_eq!(Ok(result.cost), cheapest, "{name}");
+                }
+            }
+        }
+
+        grid.neighborhood = Neighborhood::Four;
+        let result = find_path(&grid, (0, 0), (3, 0), AlgorithmType::AStar, &options).unwrap();
+        assert_eq!(result.cost, 7 * STRAIGHT_COST);
+    }
+
+    #[test]
+    fn test_find_path_errors() {
+        let mut grid = Grid::new(3, 3);
+        grid.set(1, 0, Cell::Wall);
+        grid.set(1, 1, Cell::Wall);
+        grid.set(1, 2, Cell::Wall);
+        let options = SearchOptions::default();
+        let find = |start, end| find_path(&grid, start, end, AlgorithmType::AStar, &options);
+
+        assert_eq!(find((0, 0), (3, 0)), Err(PathError::OutOfBounds((3, 0))));
+        assert_eq!(find((1, 0), (2, 0)), Err(PathError::StartIsWall));
+        assert_eq!(find((0, 0), (1, 2)), Err(PathError::EndIsWall));
+        assert_eq!(find((0, 0), (2, 2)), Err(PathError::NoPath));
+    }
+
+    #[test]
+    fn test_jps_only_on_supported_grids() {
+        let mut grid = Grid::new(4, 4);
+        let options = SearchOptions::default();
+        let find = |grid: &Grid| find_path(grid, (0, 0), (3, 3), AlgorithmType::Jps, &options);
+        let unsupported = Err(PathError::UnsupportedGrid("JPS"));
+
+        for &neighborhood in Neighborhood::all() {
+            grid.neighborhood = neighborhood;
+            if neighborhood != Neighborhood::EightNoCornerCutting {
+                assert_eq!(AlgorithmType::Dfs.next_on(&grid), AlgorithmType::BiBfs);
+            }
+            if neighborhood == Neighborhood::EightNoCornerCutting {
+                assert_eq!(find(&grid).unwrap().cost, 3 * DIAGONAL_COST);
+            } else {
+                assert_eq!(find(&grid), unsupported, "{}", neighborhood.name());
+            }
+        }
+        grid.neighborhood = Neighborhood::EightNoCornerCutting;
+        assert_eq!(AlgorithmType::Dfs.next_on(&grid), AlgorithmType::Jps);
+
+        grid.set_terrain(1, 1, Terrain::Mud);
+        assert_eq!(find(&grid), unsupported);
+        assert_eq!(AlgorithmType::Dfs.next_on(&grid), AlgorithmType::BiBfs);
+
+        // Terrain that costs the same everywhere still works
+        for y in 0..4 {
+            for x in 0..4 {
+                grid.set_terrain(x, y, Terrain::Mud);
+            }
+        }
+        let mud = grid.terrain_cost(Terrain::Mud);
+        assert_eq!(find(&grid).unwrap().cost, 3 * DIAGONAL_COST * mud);
+    }
+
+    #[test]
+    fn test_wrap_and_portals() {
+        // A wall splits the grid, the way around is over the left and right edges
+        let mut grid = Grid::new(10, 6);
+        grid.neighborhood = Neighborhood::EightNoCornerCutting;
+        for y in 0..6 {
+            grid.set(4, y, Cell::Wall);
+        }
+        let options = SearchOptions::default();
+        let find = |grid: &Grid, algorithm| find_path(grid, (2, 2), (6, 2), algorithm, &options);
+        assert_eq!(find(&grid, AlgorithmType::AStar), Err(PathError::NoPath));
+
+        grid.wrap = Wrap::Horizontal;
+        let around = find(&grid, AlgorithmType::Dijkstra).unwrap();
+        assert_eq!(around.cost, 6 * STRAIGHT_COST);
+
+        grid.add_portal((2, 0), (6, 5), 5).unwrap();
+        for &algorithm in AlgorithmType::all() {
+            let result = find(&grid, algorithm).unwrap();
+            let name = algorithm.name();
+            assert!(is_valid_path(&grid, &result.path), "{name}: invalid move");
+            assert_eq!(result.cost, grid.path_cost(&result.path), "{name}");
+            if is_optimal(algorithm) {
+                assert_eq!(result.cost, 5 * STRAIGHT_COST + 5, "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_hex_torus_with_odd_width() {
+        let mut grid = Grid::new(7, 6);
+        grid.neighborhood = Neighborhood::Hex(HexOrientation::Flat);
+        grid.wrap = Wrap::Torus;
+        // Only the top and bottom edges meet, the width being odd
+        for y in 1..5 {
+            grid.set(3, y, Cell::Wall);
+        }
+        let options = SearchOptions::default();
+
+        let open: Vec<_> = (0..6)
+            .flat_map(|y| (0..7).map(move |x| (x, y)))
+            .filter(|&(x, y)| grid.is_passable(x, y))
+            .collect();
+        for (&start, &end) in open.iter().flat_map(|s| open.iter().map(move |e| (s, e))) {
+            let best = find_path(&grid, start, end, AlgorithmType::Dijkstra, &options).unwrap();
+            for &algorithm in AlgorithmType::all().iter().filter(|a| a.supports(&grid)) {
+                let result = find_path(&grid, start, end, algorithm, &options).unwrap();
+                let name = algorithm.name();
+                assert!(is_valid_path(&grid, &result.path), "{name}: invalid move");
+                assert_eq!(result.cost, grid.path_cost(&result.path), "{name}");
+                if is_optimal(algorithm) {
+                    assert_eq!(result.cost, best.cost, "{name}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_one_way_door() {
+        // The only gap in the wall can be passed from right to left
+        let mut grid = Grid::new(6, 3);
+        grid.neighborhood = Neighborhood::EightNoCornerCutting;
+        for y in [0, 2] {
+            grid.set(3, y, Cell::Wall);
+        }
+        grid.set_one_way(3, 1, Some(Direction::Left));
+        let options = SearchOptions::default();
+
+        for &algorithm in AlgorithmType::all() {
+            let name = algorithm.name();
+            let blocked = find_path(&grid, (1, 1), (5, 1), algorithm, &options);
+            assert_eq!(blocked, Err(PathError::NoPath), "{name}");
+
+            let result = find_path(&grid, (5, 1), (1, 1), algorithm, &options).unwrap();
+            assert!(is_valid_path(&grid, &result.path), "{name}: invalid move");
+            assert_eq!(result.cost, grid.path_cost(&result.path), "{name}");
+        }
+        let result = find_path(&grid, (5, 1), (1, 1), AlgorithmType::AStar, &options).unwrap();
+        assert_eq!(result.cost, 4 * STRAIGHT_COST);
+    }
+
+    #[test]
+    fn test_nearest_goal() {
+        let mut grid = Grid::new(10, 5);
+        grid.neighborhood = Neighborhood::EightNoCornerCutting;
+        let options = SearchOptions::default();
+        let goals = [(9, 2), (0, 2), (5, 4)];
+
+        for &algorithm in AlgorithmType::all() {
+            let name = algorithm.name();
+            let result = find_path_to_nearest(&grid, (2, 2), &goals, algorithm, &options).unwrap();
+            assert_eq!(result.path.first(), Some(&(2, 2)), "{name}");
+            assert!(goals.contains(result.path.last().unwrap()), "{name}");
+            assert_eq!(result.cost, grid.path_cost(&result.path), "{name}");
+            if algorithm != AlgorithmType::Dfs {
+                assert_eq!(result.path.last(), Some(&(0, 2)), "{name}");
+                assert_eq!(result.path.len(), 3, "{name}");
+            }
+            if is_optimal(algorithm) {
+                assert_eq!(result.cost, 2 * STRAIGHT_COST, "{name}");
+            }
+        }
+
+        // Walled off goals are skipped for the nearest reachable one
+        for y in 0..5 {
+            grid.set(1, y, Cell::Wall);
+        }
+        for &algorithm in AlgorithmType::all() {
+            let result = find_path_to_nearest(&grid, (2, 2), &goals, algorithm, &options).unwrap();
+            assert_ne!(result.path.last(), Some(&(0, 2)), "{}", algorithm.name());
+        }
+        let result =
+            find_path_to_nearest(&grid, (2, 2), &goals, AlgorithmType::AStar, &options).unwrap();
+        assert_eq!(result.path.last(), Some(&(5, 4)));
+        let find = |goals: &[(usize, usize)]| {
+            find_path_to_nearest(&grid, (2, 2), goals, AlgorithmType::AStar, &options)
+        };
+        assert_eq!(find(&[]), Err(PathError::NoEnd));
+        assert_eq!(find(&[(1, 0), (9, 2)]), Err(PathError::EndIsWall));
+    }
+
+    #[test]
+    fn test_paths_to_all_goals() {
+        let mut grid = Grid::new(10, 5);
+        grid.neighborhood = Neighborhood::EightNoCornerCutting;
+        for y in 0..5 {
+            grid.set(1, y, Cell::Wall);
+        }
+        let options = SearchOptions::default();
+        let goals = [(9, 2), (0, 2), (5, 4), (3, 2)];
+
+        for &algorithm in AlgorithmType::all() {
+            let name = algorithm.name();
+            let results = find_paths_to_all(&grid, (2, 2), &goals, algorithm, &options).unwrap();
+            let mut reached: Vec<_> = results.iter().map(|r| *r.path.last().unwrap()).collect();
+            if algorithm != AlgorithmType::Dfs {
+                assert_eq!(reached, vec![(3, 2), (5, 4), (9, 2)], "{name}");
+            }
+            reached.sort();
+            assert_eq!(reached, vec![(3, 2), (5, 4), (9, 2)], "{name}");
+            for result in &results {
+                assert_eq!(result.path.first(), Some(&(2, 2)), "{name}");
+                assert_eq!(result.cost, grid.path_cost(&result.path), "{name}");
+            }
+        }
+
+        let unreachable =
+            find_paths_to_all(&grid, (2, 2), &[(0, 2)], AlgorithmType::AStar, &options);
+        assert_eq!(unreachable, Err(PathError::NoPath));
+    }
+}
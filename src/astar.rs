@@ -1,16 +1,21 @@
-use crate::grid::{Cell, Grid};
-use crate::pathfinding::{NodeState, PathfindingAlgorithm};
+use crate::grid::{Grid, Neighborhood, Portal, Wrap, DIAGONAL_COST, KNIGHT_COST, STRAIGHT_COST};
+use crate::hex::{self, HexOrientation};
+use crate::pathfinding::{
+    EventKind, NodeState, PathfindingAlgorithm, SearchEvent, SearchLog, SearchStats,
+};
+use crate::search_space::{NodeSetStore, NodeStore, SearchSpace};
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::BinaryHeap;
+use std::rc::Rc;
 
 #[derive(Eq, PartialEq)]
-struct Node {
-    position: (usize, usize),
+struct Node<N> {
+    position: N,
     g_cost: u32, // actual cost from start
     f_cost: u32, // g_cost + heuristic (estimated total cost)
 }
 
-impl Ord for Node {
+impl<N: Eq> Ord for Node<N> {
     fn cmp(&self, other: &Self) -> Ordering {
         match other.f_cost.cmp(&self.f_cost) {
             Ordering::Equal => self.g_cost.cmp(&other.g_cost), // higher g = closer to goal,
@@ -19,32 +24,224 @@ impl Ord for Node {
     }
 }
 
-impl PartialOrd for Node {
+impl<N: Eq> PartialOrd for Node<N> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
+fn deltas(from: (usize, usize), to: (usize, usize)) -> (u32, u32) {
+    let dx = (from.0 as i32 - to.0 as i32).unsigned_abs();
+    let dy = (from.1 as i32 - to.1 as i32).unsigned_abs();
+    (dx, dy)
+}
+
 fn heuristic_manhantan(from: (usize, usize), to: (usize, usize)) -> u32 {
-    let dx = (from.0 as i32 - to.0 as i32).abs() as u32;
-    let dy = (from.1 as i32 - to.1 as i32).abs() as u32;
-    dx + dy
-}
-
-pub struct AStar {
-    g_costs: HashMap<(usize, usize), u32>,
-    parents: HashMap<(usize, usize), (usize, usize)>,
-    visited: HashSet<(usize, usize)>,
-    node_states: HashMap<(usize, usize), NodeState>,
-    queue: BinaryHeap<Node>,
-    start: (usize, usize),
-    end: (usize, usize),
+    let (dx, dy) = deltas(from, to);
+    (dx + dy) * STRAIGHT_COST
+}
+
+// Scaled by DIAGONAL_COST / √2 instead of STRAIGHT_COST so that the rounded
+// down diagonal cost (14 instead of 14.14) is never overestimated
+fn heuristic_euclidean(from: (usize, usize), to: (usize, usize)) -> u32 {
+    let (dx, dy) = deltas(from, to);
+    let distance = ((dx * dx + dy * dy) as f64).sqrt();
+    (distance * DIAGONAL_COST as f64 / std::f64::consts::SQRT_2) as u32
+}
+
+fn heuristic_octile(from: (usize, usize), to: (usize, usize)) -> u32 {
+    let (dx, dy) = deltas(from, to);
+    let diagonal = dx.min(dy);
+    let straight = dx.max(dy) - diagonal;
+    diagonal * DIAGONAL_COST + straight * STRAIGHT_COST
+}
+
+fn heuristic_chebyshev(from: (usize, usize), to: (usize, usize)) -> u32 {
+    let (dx, dy) = deltas(from, to);
+    dx.max(dy) * STRAIGHT_COST
+}
+
+// A knight covers at most 2 cells along an axis and 3 cells in total per move
+fn heuristic_knight(from: (usize, usize), to: (usize, usize)) -> u32 {
+    let (dx, dy) = deltas(from, to);
+    let moves = dx
+        .div_ceil(2)
+        .max(dy.div_ceil(2))
+        .max((dx + dy).div_ceil(3));
+    moves * KNIGHT_COST
+}
+
+pub type HeuristicFn<N = (usize, usize)> = dyn Fn(N, N) -> u32;
+
+/// The parts of a grid that let paths beat a heuristic's straight-line
+/// estimate, see `Heuristic::on_grid`
+struct Topology<'a> {
+    size: (usize, usize),
+    wrap: Wrap,
+    portals: &'a [Portal],
+}
+
+impl<'a> Topology<'a> {
+    fn of(grid: &'a Grid) -> Self {
+        Topology {
+            size: (grid.width, grid.height),
+            wrap: grid.effective_wrap(),
+            portals: grid.portals(),
+        }
+    }
+
+    /// Estimate to the nearest copy of `to` across the wrapped borders. The
+    /// copies are laid out next to a shifted `from` so coordinates stay positive
+    fn direct(&self, heuristic: &Heuristic, from: (usize, usize), to: (usize, usize)) -> u32 {
+        if self.wrap == Wrap::Off {
+            return heuristic.estimate(from, to);
+        }
+        let copies = |from: usize, to: usize, size: usize, wraps: bool| match wraps {
+            true => (from + size, [to, to + size, to + 2 * size]),
+            false => (from, [to; 3]),
+        };
+        let (fx, xs) = copies(from.0, to.0, self.size.0, self.wrap.horizontal());
+        let (fy, ys) = copies(from.1, to.1, self.size.1, self.wrap.vertical());
+        xs.iter()
+            .flat_map(|&x| ys.iter().map(move |&y| (x, y)))
+            .map(|to| heuristic.estimate((fx, fy), to))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Paths through portals cost at least the way to the nearest portal end,
+    /// the cheapest portal and the way from the portal end nearest to `to`
+    fn estimate(&self, heuristic: &Heuristic, from: (usize, usize), to: (usize, usize)) -> u32 {
+        let direct = self.direct(heuristic, from, to);
+        let Some(cheapest) = self.portals.iter().map(|p| p.cost).min() else {
+            return direct;
+        };
+        let ends = || self.portals.iter().flat_map(|p| [p.a, p.b]);
+        let to_portal = ends().map(|end| self.direct(heuristic, from, end)).min();
+        let from_portal = ends().map(|end| self.direct(heuristic, end, to)).min();
+        let through = to_portal.unwrap_or(0) + cheapest + from_portal.unwrap_or(0);
+        direct.min(through)
+    }
+}
+
+/// Estimate of the remaining cost from a node to the goal
+#[derive(Clone, Default)]
+pub enum Heuristic {
+    #[default]
+    Manhattan,
+    Euclidean,
+    Octile,
+    Chebyshev,
+    Knight,
+    /// Moves on a hex grid of the given orientation
+    Hex(HexOrientation),
+    /// Always 0, turns A* into Dijkstra
+    Zero,
+    Custom(Rc<HeuristicFn>),
+}
+
+impl Heuristic {
+    /// The tightest admissible heuristic for the given movement rules
+    pub fn for_neighborhood(neighborhood: Neighborhood) -> Self {
+        match neighborhood {
+            Neighborhood::Four => Self::Manhattan,
+            Neighborhood::Eight | Neighborhood::EightNoCornerCutting => Self::Octile,
+            Neighborhood::Knight => Self::Knight,
+            Neighborhood::Hex(orientation) => Self::Hex(orientation),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Manhattan => "Manhattan",
+            Self::Euclidean => "Euclidean",
+            Self::Octile => "Octile",
+            Self::Chebyshev => "Chebyshev",
+            Self::Knight => "Knight",
+            Self::Hex(HexOrientation::Pointy) => "Hex, pointy-top",
+            Self::Hex(HexOrientation::Flat) => "Hex, flat-top",
+            Self::Zero => "Zero",
+            Self::Custom(_) => "Custom",
+        }
+    }
+
+    /// Cycles through the built-in heuristics
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Manhattan => Self::Euclidean,
+            Self::Euclidean => Self::Octile,
+            Self::Octile => Self::Chebyshev,
+            Self::Chebyshev => Self::Knight,
+            Self::Knight => Self::Hex(HexOrientation::Pointy),
+            Self::Hex(HexOrientation::Pointy) => Self::Hex(HexOrientation::Flat),
+            Self::Hex(HexOrientation::Flat) => Self::Zero,
+            Self::Zero | Self::Custom(_) => Self::Manhattan,
+        }
+    }
+
+    pub fn estimate(&self, from: (usize, usize), to: (usize, usize)) -> u32 {
+        match self {
+            Self::Manhattan => heuristic_manhantan(from, to),
+            Self::Euclidean => heuristic_euclidean(from, to),
+            Self::Octile => heuristic_octile(from, to),
+            Self::Chebyshev => heuristic_chebyshev(from, to),
+            Self::Knight => heuristic_knight(from, to),
+            Self::Hex(orientation) => hex::distance(*orientation, from, to) * STRAIGHT_COST,
+            Self::Zero => 0,
+            Self::Custom(f) => f(from, to),
+        }
+    }
+
+    /// `estimate` on `grid`, kept admissible across wrapped borders and
+    /// portals. Custom heuristics may be handed positions outside the grid
+    /// on grids that wrap
+    pub fn estimate_on(&self, grid: &Grid, from: (usize, usize), to: (usize, usize)) -> u32 {
+        Topology::of(grid).estimate(self, from, to)
+    }
+
+    /// This heuristic with `estimate_on` baked in for searches that don't
+    /// keep the grid around. Later changes to the wrap or portals are missed
+    pub fn on_grid(&self, grid: &Grid) -> Self {
+        if grid.effective_wrap() == Wrap::Off && grid.portals().is_empty() {
+            return self.clone();
+        }
+        let base = self.clone();
+        let (size, wrap, portals) = (
+            (grid.width, grid.height),
+            grid.effective_wrap(),
+            grid.portals().to_vec(),
+        );
+        Self::Custom(Rc::new(move |from, to| {
+            let topology = Topology {
+                size,
+                wrap,
+                portals: &portals,
+            };
+            topology.estimate(&base, from, to)
+        }))
+    }
+}
+
+pub struct AStar<S: SearchSpace = Grid> {
+    g_costs: S::Map<u32>,
+    parents: S::Map<S::Node>,
+    visited: S::Set,
+    log: SearchLog<S>,
+    queue: BinaryHeap<Node<S::Node>>,
+    stats: SearchStats,
+    start: S::Node,
+    goals: Vec<S::Node>,
+    /// The goal the path leads to, the first of `goals` until one is reached
+    end: S::Node,
+    /// Replaces `SearchSpace::heuristic` if set
+    heuristic: Option<Rc<HeuristicFn<S::Node>>>,
+    weight: f32,
     pub finished: bool,
     pub found_path: bool,
 }
 
-impl PathfindingAlgorithm for AStar {
-    fn step(&mut self, grid: &Grid) -> bool {
+impl<S: SearchSpace> PathfindingAlgorithm<S> for AStar<S> {
+    fn step(&mut self, space: &S) -> bool {
         if self.finished {
             return false;
         }
@@ -58,61 +255,65 @@ impl PathfindingAlgorithm for AStar {
         };
 
         let pos = current.position;
+        self.stats.steps += 1;
 
-        if self.visited.contains(&pos) {
+        if self.visited.contains(pos) {
+            self.stats.duplicate_pops += 1;
             return true;
         }
 
         self.visited.insert(pos);
-        self.node_states.insert(pos, NodeState::Visited);
+        self.log
+            .record(self.stats.steps, EventKind::Pop, pos, NodeState::Visited);
+        self.stats.nodes_expanded += 1;
 
-        if pos == self.end {
+        if self.goals.contains(&pos) {
+            self.end = pos;
             self.finished = true;
             self.found_path = true;
+            self.stats.path_cost = Some(current.g_cost);
             self.mark_path();
             return false;
         }
 
-        let current_g = *self.g_costs.get(&pos).unwrap_or(&u32::MAX);
+        let current_g = self.g_costs.get(pos).unwrap_or(u32::MAX);
 
-        for (nx, ny) in grid.neighbors(pos.0, pos.1) {
-            if let Some(cell) = grid.get(nx, ny) {
-                if cell == Cell::Wall {
-                    continue;
-                }
-            }
-
-            if self.visited.contains(&(nx, ny)) {
+        for (next, cost) in space.successors(pos) {
+            if self.visited.contains(next) {
                 continue;
             }
 
-            let new_g = current_g + 1;
-            let old_g = *self.g_costs.get(&(nx, ny)).unwrap_or(&u32::MAX);
+            let new_g = current_g + cost;
+            let old_g = self.g_costs.get(next).unwrap_or(u32::MAX);
 
             if new_g < old_g {
-                let new_f = new_g + heuristic_manhantan((nx, ny), self.end);
-                self.g_costs.insert((nx, ny), new_g);
-                self.parents.insert((nx, ny), pos);
+                let new_f = new_g + self.estimate(space, next);
+                self.g_costs.insert(next, new_g);
+                self.parents.insert(next, pos);
                 self.queue.push(Node {
-                    position: (nx, ny),
+                    position: next,
                     g_cost: new_g,
                     f_cost: new_f,
                 });
+                self.stats.record_push(self.queue.len());
 
-                self.node_states.insert((nx, ny), NodeState::InQueue);
+                let kind = if old_g == u32::MAX {
+                    EventKind::Push
+                } else {
+                    EventKind::Relax
+                };
+                self.log
+                    .record(self.stats.steps, kind, next, NodeState::InQueue);
             }
         }
         true
     }
 
-    fn get_node_state(&self, x: usize, y: usize) -> NodeState {
-        *self
-            .node_states
-            .get(&(x, y))
-            .unwrap_or(&NodeState::Unvisited)
+    fn get_node_state(&self, node: S::Node) -> NodeState {
+        self.log.state(node)
     }
 
-    fn get_path(&self) -> Vec<(usize, usize)> {
+    fn get_path(&self) -> Vec<S::Node> {
         if !self.found_path {
             return Vec::new();
         }
@@ -121,7 +322,7 @@ impl PathfindingAlgorithm for AStar {
 
         while current != self.start {
             path.push(current);
-            if let Some(&parent) = self.parents.get(&current) {
+            if let Some(parent) = self.parents.get(current) {
                 current = parent;
             } else {
                 break;
@@ -143,58 +344,261 @@ impl PathfindingAlgorithm for AStar {
     fn name(&self) -> &'static str {
         "A*"
     }
+
+    fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    fn events(&self) -> &[SearchEvent<S::Node>] {
+        self.log.events()
+    }
 }
 
 impl AStar {
-    pub fn new(start: (usize, usize), end: (usize, usize)) -> Self {
+    pub fn new(
+        grid: &Grid,
+        start: (usize, usize),
+        end: (usize, usize),
+        heuristic: Heuristic,
+    ) -> Self {
+        Self::to_nearest(grid, start, &[end], heuristic)
+    }
+
+    /// Stops at whichever of `goals` is the cheapest to reach, guided by the
+    /// smallest estimate to any of them
+    pub fn to_nearest(
+        grid: &Grid,
+        start: (usize, usize),
+        goals: &[(usize, usize)],
+        heuristic: Heuristic,
+    ) -> Self {
+        let heuristic = heuristic.on_grid(grid);
+        let estimate = move |from, to| heuristic.estimate(from, to);
+        Self::with_heuristic(grid, start, goals, Some(Rc::new(estimate)))
+    }
+}
+
+impl<S: SearchSpace> AStar<S> {
+    /// A* guided by `SearchSpace::heuristic`
+    pub fn for_space(space: &S, start: S::Node, end: S::Node) -> Self {
+        Self::with_heuristic(space, start, &[end], None)
+    }
+
+    fn with_heuristic(
+        space: &S,
+        start: S::Node,
+        goals: &[S::Node],
+        heuristic: Option<Rc<HeuristicFn<S::Node>>>,
+    ) -> Self {
         let mut astar = AStar {
-            g_costs: HashMap::new(),
-            parents: HashMap::new(),
-            visited: HashSet::new(),
-            node_states: HashMap::new(),
+            g_costs: space.node_map(),
+            parents: space.node_map(),
+            visited: space.node_set(),
+            log: SearchLog::new(space),
             queue: BinaryHeap::new(),
+            stats: SearchStats::default(),
             start,
-            end,
+            goals: goals.to_vec(),
+            end: goals.first().copied().unwrap_or(start),
+            heuristic,
+            weight: 1.0,
             finished: false,
             found_path: false,
         };
 
         astar.g_costs.insert(start, 0);
-        let h = heuristic_manhantan(start, end);
+        let h = astar.estimate(space, start);
         astar.queue.push(Node {
             position: start,
             g_cost: 0,
             f_cost: h, // f = g + h = 0 + h
         });
+        astar.stats.record_push(1);
+        astar
+            .log
+            .record(0, EventKind::Push, start, NodeState::InQueue);
 
         astar
     }
 
+    /// Weighted A*: multiplies the heuristic by `weight`. Values above 1.0
+    /// expand fewer nodes but the path may be up to `weight` times too long
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight.max(0.0);
+        self
+    }
+
+    /// Weighted estimate to the closest goal
+    fn estimate(&self, space: &S, pos: S::Node) -> u32 {
+        let h = self
+            .goals
+            .iter()
+            .map(|&goal| match &self.heuristic {
+                Some(heuristic) => heuristic(pos, goal),
+                None => space.heuristic(pos, goal),
+            })
+            .min()
+            .unwrap_or(0);
+        (h as f32 * self.weight).round() as u32
+    }
+
     fn mark_path(&mut self) {
         let mut current = self.end;
         while current != self.start {
-            self.node_states.insert(current, NodeState::Path);
-            if let Some(&parent) = self.parents.get(&current) {
+            self.log.record(
+                self.stats.steps,
+                EventKind::PathMark,
+                current,
+                NodeState::Path,
+            );
+            if let Some(parent) = self.parents.get(current) {
                 current = parent;
             } else {
                 break;
             }
         }
-        self.node_states.insert(self.start, NodeState::Path);
+        self.log.record(
+            self.stats.steps,
+            EventKind::PathMark,
+            self.start,
+            NodeState::Path,
+        );
     }
 }
 
 #[cfg(test)]
 mod tests {
-
     use super::*;
+    use crate::dijkstra::Dijkstra;
+    use crate::grid::{Cell, Wrap};
 
     #[test]
     fn test_heuristics() {
         let a = heuristic_manhantan((0, 0), (2, 2));
-        assert_eq!(a, 4);
+        assert_eq!(a, 4 * STRAIGHT_COST);
 
         let b = heuristic_manhantan((2, 2), (0, 0));
-        assert_eq!(b, 4);
+        assert_eq!(b, 4 * STRAIGHT_COST);
+    }
+
+    #[test]
+    fn test_heuristic_octile() {
+        let h = heuristic_octile((0, 0), (2, 3));
+        assert_eq!(h, 2 * DIAGONAL_COST + STRAIGHT_COST);
+    }
+
+    #[test]
+    fn test_heuristic_knight() {
+        assert_eq!(heuristic_knight((0, 0), (1, 2)), KNIGHT_COST);
+        assert_eq!(heuristic_knight((0, 0), (4, 0)), 2 * KNIGHT_COST);
+        assert_eq!(heuristic_knight((3, 3), (3, 3)), 0);
+    }
+
+    #[test]
+    fn test_heuristic_euclidean_is_admissible_on_diagonals() {
+        let h = heuristic_euclidean((0, 0), (100, 100));
+        assert!(h <= heuristic_octile((0, 0), (100, 100)));
+        assert_eq!(heuristic_euclidean((0, 0), (0, 3)), 29);
+    }
+
+    #[test]
+    fn test_custom_heuristic() {
+        let h = Heuristic::Custom(Rc::new(|from, to| heuristic_chebyshev(from, to) * 2));
+        assert_eq!(h.estimate((0, 0), (3, 1)), 6 * STRAIGHT_COST);
+        assert_eq!(Heuristic::Zero.estimate((0, 0), (3, 1)), 0);
+    }
+
+    #[test]
+    fn test_weighted_path_still_reaches_goal() {
+        let mut grid = Grid::new(5, 5);
+        grid.set(2, 1, Cell::Wall);
+        grid.set(2, 2, Cell::Wall);
+        let mut astar = AStar::new(&grid, (0, 2), (4, 2), Heuristic::Manhattan).with_weight(3.0);
+        while astar.step(&grid) {}
+
+        let path = astar.get_path();
+        assert_eq!(path.first(), Some(&(0, 2)));
+        assert_eq!(path.last(), Some(&(4, 2)));
+    }
+
+    #[test]
+    fn test_diagonal_path() {
+        let mut grid = Grid::new(4, 4);
+        grid.neighborhood = Neighborhood::Eight;
+        let mut astar = AStar::new(&grid, (0, 0), (3, 3), Heuristic::Octile);
+        while astar.step(&grid) {}
+
+        assert_eq!(astar.get_path(), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+        assert_eq!(astar.g_costs.get((3, 3)).unwrap(), 3 * DIAGONAL_COST);
+    }
+
+    #[test]
+    fn test_hex_heuristic_matches_dijkstra() {
+        for orientation in [HexOrientation::Pointy, HexOrientation::Flat] {
+            let mut grid = Grid::new(12, 9);
+            grid.neighborhood = Neighborhood::Hex(orientation);
+            for y in 1..8 {
+                grid.set(6, y, Cell::Wall);
+            }
+            let (start, end) = ((1, 4), (11, 5));
+
+            let mut astar = AStar::new(&grid, start, end, Heuristic::Hex(orientation));
+            let mut dijkstra = Dijkstra::new(&grid, start, end);
+            let cost = astar.run_to_end(&grid).unwrap().cost;
+            assert_eq!(Some(cost), dijkstra.run_to_end(&grid).map(|r| r.cost));
+            assert!(astar.stats().nodes_expanded < dijkstra.stats().nodes_expanded);
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut grid = Grid::new(3, 3);
+        grid.set(1, 0, Cell::Wall);
+        grid.set(1, 1, Cell::Wall);
+        let mut astar = AStar::new(&grid, (0, 0), (2, 0), Heuristic::Manhattan);
+        while astar.step(&grid) {}
+
+        let stats = astar.stats();
+        assert_eq!(stats.nodes_expanded, 7);
+        assert_eq!(stats.nodes_pushed, 7);
+        assert_eq!(stats.steps, 7);
+        assert_eq!(stats.duplicate_pops, 0);
+        assert_eq!(stats.path_cost, Some(6 * STRAIGHT_COST));
+    }
+
+    #[test]
+    fn test_heuristics_admissible_on_wrapped_grid_with_portals() {
+        let mut grid = Grid::new(12, 9);
+        grid.wrap = Wrap::Torus;
+        grid.neighborhood = Neighborhood::Eight;
+        grid.add_portal((1, 1), (6, 4), 4).unwrap();
+        grid.add_portal((10, 8), (3, 6), 15).unwrap();
+
+        // Searching for a wall explores the whole grid
+        grid.set(0, 8, Cell::Wall);
+        let goal = (9, 2);
+        let mut dijkstra = Dijkstra::new(&grid, goal, (0, 8));
+        while dijkstra.step(&grid) {}
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                let Some(cost) = dijkstra.distances.get((x, y)) else {
+                    continue;
+                };
+                for heuristic in [
+                    Heuristic::Octile,
+                    Heuristic::Chebyshev,
+                    Heuristic::Euclidean,
+                ] {
+                    let estimate = heuristic.estimate_on(&grid, (x, y), goal);
+                    assert!(estimate <= cost, "{} at {:?}", heuristic.name(), (x, y));
+                    assert_eq!(heuristic.on_grid(&grid).estimate((x, y), goal), estimate);
+                }
+            }
+        }
+        // The short way around is still a useful estimate
+        assert_eq!(
+            Heuristic::Octile.estimate_on(&grid, (11, 2), (0, 2)),
+            STRAIGHT_COST
+        );
     }
 }
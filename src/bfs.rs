@@ -1,33 +1,48 @@
-use crate::grid::{Cell, Grid};
-use crate::pathfinding::{NodeState, PathfindingAlgorithm};
-use std::collections::{HashMap, HashSet, VecDeque};
-
-pub struct Bfs {
-    queue: VecDeque<(usize, usize)>,
-    visited: HashSet<(usize, usize)>,
-    parents: HashMap<(usize, usize), (usize, usize)>,
-    node_states: HashMap<(usize, usize), NodeState>,
-    start: (usize, usize),
-    end: (usize, usize),
+use crate::grid::Grid;
+use crate::pathfinding::{
+    EventKind, NodeState, PathfindingAlgorithm, SearchEvent, SearchLog, SearchStats,
+};
+use crate::search_space::{NodeSetStore, NodeStore, SearchSpace};
+use std::collections::VecDeque;
+
+pub struct Bfs<S: SearchSpace = Grid> {
+    queue: VecDeque<S::Node>,
+    visited: S::Set,
+    parents: S::Map<S::Node>,
+    log: SearchLog<S>,
+    stats: SearchStats,
+    start: S::Node,
+    goals: Vec<S::Node>,
+    /// The goal the path leads to, the first of `goals` until one is reached
+    end: S::Node,
     finished: bool,
     found_path: bool,
 }
 
-impl Bfs {
-    pub fn new(start: (usize, usize), end: (usize, usize)) -> Self {
+impl<S: SearchSpace> Bfs<S> {
+    pub fn new(space: &S, start: S::Node, end: S::Node) -> Self {
+        Self::to_nearest(space, start, &[end])
+    }
+
+    /// Stops at whichever of `goals` takes the fewest moves to reach
+    pub fn to_nearest(space: &S, start: S::Node, goals: &[S::Node]) -> Self {
         let mut bfs = Self {
             queue: VecDeque::new(),
-            visited: HashSet::new(),
-            parents: HashMap::new(),
-            node_states: HashMap::new(),
+            visited: space.node_set(),
+            parents: space.node_map(),
+            log: SearchLog::new(space),
+            stats: SearchStats::default(),
             start,
-            end,
+            goals: goals.to_vec(),
+            end: goals.first().copied().unwrap_or(start),
             finished: false,
             found_path: false,
         };
 
         bfs.queue.push_back(start);
-        bfs.node_states.insert(start, NodeState::InQueue);
+        bfs.log
+            .record(bfs.stats.steps, EventKind::Push, start, NodeState::InQueue);
+        bfs.stats.record_push(1);
 
         bfs
     }
@@ -35,19 +50,29 @@ impl Bfs {
     fn mark_path(&mut self) {
         let mut current = self.end;
         while current != self.start {
-            self.node_states.insert(current, NodeState::Path);
-            if let Some(&parent) = self.parents.get(&current) {
+            self.log.record(
+                self.stats.steps,
+                EventKind::PathMark,
+                current,
+                NodeState::Path,
+            );
+            if let Some(parent) = self.parents.get(current) {
                 current = parent;
             } else {
                 break;
             }
         }
-        self.node_states.insert(self.start, NodeState::Path);
+        self.log.record(
+            self.stats.steps,
+            EventKind::PathMark,
+            self.start,
+            NodeState::Path,
+        );
     }
 }
 
-impl PathfindingAlgorithm for Bfs {
-    fn step(&mut self, grid: &Grid) -> bool {
+impl<S: SearchSpace> PathfindingAlgorithm<S> for Bfs<S> {
+    fn step(&mut self, space: &S) -> bool {
         if self.finished {
             return false;
         }
@@ -60,51 +85,54 @@ impl PathfindingAlgorithm for Bfs {
             }
         };
 
-        if self.visited.contains(&current) {
+        self.stats.steps += 1;
+        if self.visited.contains(current) {
+            self.stats.duplicate_pops += 1;
             return true;
         }
 
         self.visited.insert(current);
-        self.node_states.insert(current, NodeState::Visited);
-
-        if current == self.end {
+        self.log.record(
+            self.stats.steps,
+            EventKind::Pop,
+            current,
+            NodeState::Visited,
+        );
+        self.stats.nodes_expanded += 1;
+
+        if self.goals.contains(&current) {
+            self.end = current;
             self.finished = true;
             self.found_path = true;
+            self.stats.path_cost = Some(space.path_cost(&self.get_path()));
             self.mark_path();
             return false;
         }
 
-        for (nx, ny) in grid.neighbors(current.0, current.1) {
-            if let Some(cell) = grid.get(nx, ny) {
-                if cell == Cell::Wall {
-                    continue;
-                }
-            }
-
-            if self.visited.contains(&(nx, ny)) {
+        for (next, _) in space.successors(current) {
+            if self.visited.contains(next) {
                 continue;
             }
 
-            if self.node_states.get(&(nx, ny)) == Some(&NodeState::InQueue) {
+            if self.log.state(next) == NodeState::InQueue {
                 continue;
             }
 
-            self.parents.insert((nx, ny), current);
-            self.queue.push_back((nx, ny));
-            self.node_states.insert((nx, ny), NodeState::InQueue);
+            self.parents.insert(next, current);
+            self.queue.push_back(next);
+            self.stats.record_push(self.queue.len());
+            self.log
+                .record(self.stats.steps, EventKind::Push, next, NodeState::InQueue);
         }
 
         true
     }
 
-    fn get_node_state(&self, x: usize, y: usize) -> NodeState {
-        *self
-            .node_states
-            .get(&(x, y))
-            .unwrap_or(&NodeState::Unvisited)
+    fn get_node_state(&self, node: S::Node) -> NodeState {
+        self.log.state(node)
     }
 
-    fn get_path(&self) -> Vec<(usize, usize)> {
+    fn get_path(&self) -> Vec<S::Node> {
         if !self.found_path {
             return Vec::new();
         }
@@ -114,7 +142,7 @@ impl PathfindingAlgorithm for Bfs {
 
         while current != self.start {
             path.push(current);
-            if let Some(&parent) = self.parents.get(&current) {
+            if let Some(parent) = self.parents.get(current) {
                 current = parent;
             } else {
                 break;
@@ -136,4 +164,12 @@ impl PathfindingAlgorithm for Bfs {
     fn name(&self) -> &'static str {
         "BFS"
     }
+
+    fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    fn events(&self) -> &[SearchEvent<S::Node>] {
+        self.log.events()
+    }
 }
@@ -0,0 +1,471 @@
+use crate::astar::Heuristic;
+use crate::grid::Grid;
+use crate::node_map::{NodeMap, NodeSet};
+use crate::pathfinding::{
+    EventKind, NodeState, PathfindingAlgorithm, SearchEvent, SearchLog, SearchStats,
+};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BidirectionalMode {
+    Bfs,
+    Dijkstra,
+    AStar,
+}
+
+#[derive(Eq, PartialEq)]
+struct Node {
+    position: (usize, usize),
+    g_cost: u32,
+    f_cost: u32,
+    order: u64, // insertion order, ties are popped first-in first-out
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match other.f_cost.cmp(&self.f_cost) {
+            Ordering::Equal => other.order.cmp(&self.order),
+            other => other,
+        }
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// One of the two searches, growing from its origins towards its targets
+struct Side {
+    g_costs: NodeMap<u32>,
+    parents: NodeMap<(usize, usize)>,
+    closed: NodeSet,
+    queue: BinaryHeap<Node>,
+    targets: Vec<(usize, usize)>,
+}
+
+impl Side {
+    fn new(
+        grid: &Grid,
+        origins: &[(usize, usize)],
+        targets: &[(usize, usize)],
+        heuristic: &Heuristic,
+    ) -> Self {
+        let mut side = Side {
+            g_costs: NodeMap::for_grid(grid),
+            parents: NodeMap::for_grid(grid),
+            closed: NodeSet::for_grid(grid),
+            queue: BinaryHeap::new(),
+            targets: targets.to_vec(),
+        };
+        for &origin in origins {
+            side.g_costs.insert(origin, 0);
+            side.queue.push(Node {
+                position: origin,
+                g_cost: 0,
+                f_cost: side.estimate(heuristic, origin),
+                order: 0,
+            });
+        }
+        side
+    }
+
+    /// Estimate to the closest target
+    fn estimate(&self, heuristic: &Heuristic, pos: (usize, usize)) -> u32 {
+        self.targets
+            .iter()
+            .map(|&target| heuristic.estimate(pos, target))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Drop queue entries that were already expanded or have been improved
+    /// on, returns how many were dropped
+    fn prune(&mut self) -> usize {
+        let mut pruned = 0;
+        while let Some(top) = self.queue.peek() {
+            let stale = self.closed.contains(top.position)
+                || self.g_costs.get(top.position) != Some(top.g_cost);
+            if !stale {
+                break;
+            }
+            self.queue.pop();
+            pruned += 1;
+        }
+        pruned
+    }
+
+    /// Cells from `from` back to one of this side's origins, `from` included
+    fn trace(&self, from: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut cells = vec![from];
+        let mut current = from;
+        while let Some(parent) = self.parents.get(current) {
+            cells.push(parent);
+            current = parent;
+        }
+        cells
+    }
+}
+
+/// Runs one search from the start and one from the end and stops once no
+/// better meeting point can exist. The side with the smaller queue grows next.
+/// With several goals the backward search starts from all of them at once.
+///
+/// Stopping criteria: BFS and Dijkstra stop when the smallest distances in
+/// both queues add up to at least the best path seen. A* stops when either
+/// queue's smallest f cost reaches the best path, which is only optimal for
+/// consistent heuristics.
+pub struct Bidirectional {
+    forward: Side,
+    backward: Side,
+    log: SearchLog,
+    stats: SearchStats,
+    mode: BidirectionalMode,
+    heuristic: Heuristic,
+    best_cost: u32,
+    meeting: Option<(usize, usize)>,
+    order: u64,
+    start: (usize, usize),
+    goals: Vec<(usize, usize)>,
+    finished: bool,
+    found_path: bool,
+}
+
+impl Bidirectional {
+    pub fn bfs(grid: &Grid, start: (usize, usize), end: (usize, usize)) -> Self {
+        Self::to_nearest(grid, start, &[end], BidirectionalMode::Bfs, Heuristic::Zero)
+    }
+
+    pub fn dijkstra(grid: &Grid, start: (usize, usize), end: (usize, usize)) -> Self {
+        Self::to_nearest(
+            grid,
+            start,
+            &[end],
+            BidirectionalMode::Dijkstra,
+            Heuristic::Zero,
+        )
+    }
+
+    pub fn astar(
+        grid: &Grid,
+        start: (usize, usize),
+        end: (usize, usize),
+        heuristic: Heuristic,
+    ) -> Self {
+        Self::to_nearest(grid, start, &[end], BidirectionalMode::AStar, heuristic)
+    }
+
+    /// Stops at whichever of `goals` is the cheapest to reach, or the one
+    /// with the fewest moves in BFS mode. `heuristic` only guides the A* mode
+    pub fn to_nearest(
+        grid: &Grid,
+        start: (usize, usize),
+        goals: &[(usize, usize)],
+        mode: BidirectionalMode,
+        heuristic: Heuristic,
+    ) -> Self {
+        let heuristic = match mode {
+            BidirectionalMode::AStar => heuristic.on_grid(grid),
+            _ => Heuristic::Zero,
+        };
+        let mut bidirectional = Bidirectional {
+            forward: Side::new(grid, &[start], goals, &heuristic),
+            backward: Side::new(grid, goals, &[start], &heuristic),
+            log: SearchLog::new(grid),
+            stats: SearchStats::default(),
+            mode,
+            heuristic,
+            best_cost: u32::MAX,
+            meeting: None,
+            order: 1,
+            start,
+            goals: goals.to_vec(),
+            finished: false,
+            found_path: false,
+        };
+
+        if goals.contains(&start) {
+            bidirectional.best_cost = 0;
+            bidirectional.meeting = Some(start);
+        }
+        bidirectional
+            .log
+            .record(0, EventKind::Push, start, NodeState::InQueue);
+        bidirectional.stats.record_push(1);
+        for (i, &goal) in goals.iter().enumerate() {
+            bidirectional
+                .log
+                .record(0, EventKind::Push, goal, NodeState::BackwardInQueue);
+            bidirectional.stats.record_push(2 + i);
+        }
+        bidirectional
+    }
+
+    fn should_stop(&self) -> bool {
+        let (Some(f), Some(b)) = (self.forward.queue.peek(), self.backward.queue.peek()) else {
+            return true;
+        };
+
+        match self.mode {
+            BidirectionalMode::AStar => f.f_cost.max(b.f_cost) >= self.best_cost,
+            _ => f.g_cost.saturating_add(b.g_cost) >= self.best_cost,
+        }
+    }
+
+    fn expand(&mut self, grid: &Grid, forward: bool) {
+        let (side, other) = if forward {
+            (&mut self.forward, &self.backward)
+        } else {
+            (&mut self.backward, &self.forward)
+        };
+        let (in_queue, visited) = if forward {
+            (NodeState::InQueue, NodeState::Visited)
+        } else {
+            (NodeState::BackwardInQueue, NodeState::BackwardVisited)
+        };
+
+        let Some(current) = side.queue.pop() else {
+            return;
+        };
+        let pos = current.position;
+        side.closed.insert(pos);
+        self.stats.steps += 1;
+        self.stats.nodes_expanded += 1;
+        self.log
+            .record(self.stats.steps, EventKind::Pop, pos, visited);
+
+        let next = if forward {
+            grid.neighbors(pos.0, pos.1)
+        } else {
+            grid.predecessors(pos.0, pos.1)
+        };
+        for (nx, ny) in next.passable() {
+            if side.closed.contains((nx, ny)) {
+                continue;
+            }
+
+            // The backward search walks edges in reverse, so it pays for
+            // entering the cell it came from
+            let cost = match self.mode {
+                BidirectionalMode::Bfs => 1,
+                _ if forward => grid.move_cost(pos, (nx, ny)),
+                _ => grid.move_cost((nx, ny), pos),
+            };
+            let new_g = current.g_cost + cost;
+            let old_g = side.g_costs.get((nx, ny)).unwrap_or(u32::MAX);
+
+            if new_g < old_g {
+                side.g_costs.insert((nx, ny), new_g);
+                side.parents.insert((nx, ny), pos);
+                side.queue.push(Node {
+                    position: (nx, ny),
+                    g_cost: new_g,
+                    f_cost: new_g + side.estimate(&self.heuristic, (nx, ny)),
+                    order: self.order,
+                });
+                self.order += 1;
+                self.stats.record_push(side.queue.len() + other.queue.len());
+
+                // Cells already reached by the other side keep its colour
+                let state = match self.log.state((nx, ny)) {
+                    NodeState::Unvisited => in_queue,
+                    state => state,
+                };
+                let kind = if old_g == u32::MAX {
+                    EventKind::Push
+                } else {
+                    EventKind::Relax
+                };
+                self.log.record(self.stats.steps, kind, (nx, ny), state);
+
+                if let Some(other_g) = other.g_costs.get((nx, ny)) {
+                    if new_g + other_g < self.best_cost {
+                        self.best_cost = new_g + other_g;
+                        self.meeting = Some((nx, ny));
+                    }
+                }
+            }
+        }
+    }
+
+    fn mark_path(&mut self) {
+        for pos in self.get_path() {
+            self.log
+                .record(self.stats.steps, EventKind::PathMark, pos, NodeState::Path);
+        }
+    }
+}
+
+impl PathfindingAlgorithm for Bidirectional {
+    fn step(&mut self, grid: &Grid) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        self.stats.duplicate_pops += self.forward.prune() + self.backward.prune();
+
+        if self.should_stop() {
+            self.finished = true;
+            self.found_path = self.meeting.is_some();
+            if self.found_path {
+                self.stats.path_cost = Some(grid.path_cost(&self.get_path()));
+                self.mark_path();
+            }
+            return false;
+        }
+
+        let forward = match self.forward.queue.len().cmp(&self.backward.queue.len()) {
+            Ordering::Less => true,
+            Ordering::Greater => false,
+            Ordering::Equal => self.forward.closed.len() <= self.backward.closed.len(),
+        };
+        self.expand(grid, forward);
+        true
+    }
+
+    fn get_node_state(&self, pos: (usize, usize)) -> NodeState {
+        self.log.state(pos)
+    }
+
+    fn get_path(&self) -> Vec<(usize, usize)> {
+        let Some(meeting) = self.meeting else {
+            return Vec::new();
+        };
+
+        let mut path = self.forward.trace(meeting);
+        path.reverse();
+        path.extend(self.backward.trace(meeting).into_iter().skip(1));
+
+        let reaches_goal = path.last().is_some_and(|last| self.goals.contains(last));
+        if path.first() != Some(&self.start) || !reaches_goal {
+            return Vec::new();
+        }
+        path
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn found_path(&self) -> bool {
+        self.found_path
+    }
+
+    fn name(&self) -> &'static str {
+        match self.mode {
+            BidirectionalMode::Bfs => "Bi-BFS",
+            BidirectionalMode::Dijkstra => "Bi-Dijkstra",
+            BidirectionalMode::AStar => "Bi-A*",
+        }
+    }
+
+    fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    fn events(&self) -> &[SearchEvent] {
+        self.log.events()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bfs::Bfs;
+    use crate::cellular_automata::CellularAutomata;
+    use crate::dijkstra::Dijkstra;
+    use crate::grid::{Cell, Neighborhood, Terrain};
+    use crate::map_generator::MapGenerator;
+
+    fn cave(seed: u64) -> Grid {
+        let mut grid = Grid::new(40, 30);
+        CellularAutomata {
+            seed,
+            ..Default::default()
+        }
+        .generate(&mut grid);
+        grid.neighborhood = Neighborhood::Eight;
+        for y in 0..grid.height {
+            for x in (y % 3..grid.width).step_by(3) {
+                grid.set_terrain(x, y, Terrain::Mud);
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn test_weighted_modes_match_dijkstra() {
+        for seed in 1..5 {
+            let grid = cave(seed);
+            let [start, end] = grid.endpoints().unwrap();
+
+            let mut dijkstra = Dijkstra::new(&grid, start, end);
+            while dijkstra.step(&grid) {}
+            let optimal = dijkstra.distances.get(end).unwrap();
+
+            let mut searches = [
+                Bidirectional::dijkstra(&grid, start, end),
+                Bidirectional::astar(&grid, start, end, Heuristic::Octile),
+            ];
+            for search in searches.iter_mut() {
+                while search.step(&grid) {}
+                assert!(search.found_path());
+                let path = search.get_path();
+                assert_eq!(grid.path_cost(&path), optimal, "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bfs_mode_matches_bfs_length() {
+        for seed in 1..5 {
+            let grid = cave(seed);
+            let [start, end] = grid.endpoints().unwrap();
+
+            let mut bfs = Bfs::new(&grid, start, end);
+            while bfs.step(&grid) {}
+            let mut bidirectional = Bidirectional::bfs(&grid, start, end);
+            while bidirectional.step(&grid) {}
+
+            let path = bidirectional.get_path();
+            assert_eq!(path.len(), bfs.get_path().len(), "seed {seed}");
+            assert!(path
+                .windows(2)
+                .all(|pair| grid.neighbors(pair[0].0, pair[0].1).any(|n| n == pair[1])));
+        }
+    }
+
+    #[test]
+    fn test_no_path() {
+        let mut grid = Grid::new(3, 3);
+        for y in 0..3 {
+            grid.set(1, y, Cell::Wall);
+        }
+        let mut bidirectional = Bidirectional::dijkstra(&grid, (0, 0), (2, 2));
+        while bidirectional.step(&grid) {}
+
+        assert!(bidirectional.is_finished());
+        assert!(!bidirectional.found_path());
+        assert!(bidirectional.get_path().is_empty());
+    }
+
+    #[test]
+    fn test_frontiers_are_told_apart() {
+        let grid = Grid::new(5, 1);
+        let mut bidirectional = Bidirectional::bfs(&grid, (0, 0), (4, 0));
+        bidirectional.step(&grid);
+        bidirectional.step(&grid);
+
+        assert_eq!(bidirectional.get_node_state((0, 0)), NodeState::Visited);
+        assert_eq!(
+            bidirectional.get_node_state((4, 0)),
+            NodeState::BackwardVisited
+        );
+        assert_eq!(
+            bidirectional.get_node_state((3, 0)),
+            NodeState::BackwardInQueue
+        );
+    }
+}
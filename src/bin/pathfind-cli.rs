@@ -0,0 +1,488 @@
+use path_finding::{
+    algorithms::{try_create_algorithm_to_nearest, AlgorithmType},
+    astar::Heuristic,
+    cellular_automata::{CellularAutomata, SmoothingPass},
+    grid::{Cell, Grid, Neighborhood, Portal, Wrap, STRAIGHT_COST},
+    map_file,
+    map_generator::generators,
+    movingai,
+    pathfinding::{PathError, SearchStats},
+};
+use std::{env, fs, process, time::Instant};
+
+const USAGE: &str = "\
+Usage: pathfind-cli [OPTIONS]
+
+Runs a search to completion and prints the result. Without --map a map is
+generated, a cellular automata cave unless --generator says otherwise.
+
+Options:
+  --map <FILE>             Load a map, MovingAI format if it ends in .map,
+                           otherwise the plain-text format of the app
+  --generator <NAME>       cave, backtracker-maze, prim-maze, kruskal-maze,
+                           wilson-maze, eller-maze or division-maze
+                           [default: cave]
+  --width <N>              Width of the generated map [default: 50]
+  --height <N>             Height of the generated map [default: 50]
+  --seed <N>               Seed of the generated map [default: 12345]
+  --wall-chance <F>        Initial wall probability of caves [default: 0.45]
+  --smoothing <N>          Smoothing passes of caves with the default rule
+                           [default: 1]
+  --pass <RULE[:RADIUS]>   Add a cave smoothing pass with a B/S rule such as
+                           B5678/S345678 and a neighbor radius [default: 1],
+                           replaces --smoothing, repeat for more passes
+  --braid <F>              Share of maze dead ends to open [default: 0]
+  --algorithm <NAME>       dijkstra, astar, bfs, dfs, jps, bi-bfs, bi-dijkstra,
+                           bi-astar or dstar-lite [default: astar]. jps needs
+                           8-way-no-corner-cutting and uniform terrain
+  --heuristic <NAME>       manhattan, euclidean, octile, chebyshev, knight,
+                           hex-pointy-top, hex-flat-top or zero
+                           [default: the best for the neighborhood]
+  --weight <F>             Weight of the A* heuristic [default: 1]
+  --neighborhood <NAME>    4-way, 8-way, 8-way-no-corner-cutting, knight,
+                           hex-pointy-top or hex-flat-top. Generated caves
+                           are smoothed for it
+  --wrap <NAME>            off, horizontal, vertical or torus: which edges
+                           lead around to the other side [default: off].
+                           Hex grids wrap an even number of offset rows or
+                           columns only
+  --portal <X,Y:X,Y[:COST]>
+                           Link two cells with a portal that costs COST to
+                           step through [default: 10], repeat for more
+  --start <X,Y>            Start cell, overrides the one in the map
+  --end <X,Y>              End cell, overrides the ones in the map. Repeat
+                           for more, the search stops at the nearest
+  --json                   Print the result as JSON
+  -h, --help               Print this help
+
+Exits with 1 if no path exists and 2 on invalid input.";
+
+struct Options {
+    map: Option<String>,
+    width: usize,
+    height: usize,
+    /// Index into `generators`
+    generator: usize,
+    seed: u64,
+    cave: CellularAutomata,
+    braid: f32,
+    algorithm: AlgorithmType,
+    heuristic: Option<Heuristic>,
+    weight: f32,
+    neighborhood: Option<Neighborhood>,
+    wrap: Wrap,
+    portals: Vec<Portal>,
+    start: Option<(usize, usize)>,
+    ends: Vec<(usize, usize)>,
+    json: bool,
+}
+
+/// Lowercase letters and digits only, `*` spelled out, so that "Bi-A*"
+/// matches `bi-astar` or `biastar` on the command line
+fn slug(name: &str) -> String {
+    name.replace('*', "star")
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn find_by_name<T: Clone>(all: &[T], name: impl Fn(&T) -> &'static str, arg: &str) -> Option<T> {
+    all.iter()
+        .find(|item| slug(name(item)) == slug(arg))
+        .cloned()
+}
+
+fn heuristics() -> Vec<Heuristic> {
+    let mut all = vec![Heuristic::default()];
+    loop {
+        let next = all.last().unwrap().next();
+        if next.name() == all[0].name() {
+            return all;
+        }
+        all.push(next);
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}' for {flag}"))
+}
+
+fn parse_position(flag: &str, value: &str) -> Result<(usize, usize), String> {
+    let (x, y) = value
+        .split_once(',')
+        .ok_or_else(|| format!("{flag} expects X,Y, got '{value}'"))?;
+    Ok((parse_value(flag, x.trim())?, parse_value(flag, y.trim())?))
+}
+
+fn parse_pass(flag: &str, value: &str) -> Result<SmoothingPass, String> {
+    let (rule, radius) = value.split_once(':').unwrap_or((value, "1"));
+    Ok(SmoothingPass {
+        rule: Some(
+            rule.parse()
+                .map_err(|err| format!("invalid value '{value}' for {flag}: {err}"))?,
+        ),
+        radius: parse_value(flag, radius)?,
+        ..Default::default()
+    })
+}
+
+fn parse_portal(flag: &str, value: &str) -> Result<Portal, String> {
+    let mut parts = value.split(':');
+    let (Some(a), Some(b)) = (parts.next(), parts.next()) else {
+        return Err(format!("{flag} expects X,Y:X,Y[:COST], got '{value}'"));
+    };
+    let cost = match parts.next() {
+        Some(cost) => parse_value(flag, cost)?,
+        None => STRAIGHT_COST,
+    };
+    if parts.next().is_some() {
+        return Err(format!("{flag} expects X,Y:X,Y[:COST], got '{value}'"));
+    }
+    Ok(Portal {
+        a: parse_position(flag, a)?,
+        b: parse_position(flag, b)?,
+        cost,
+    })
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        map: None,
+        width: 50,
+        height: 50,
+        generator: 0,
+        seed: 12345,
+        cave: CellularAutomata::default(),
+        braid: 0.0,
+        algorithm: AlgorithmType::default(),
+        heuristic: None,
+        weight: 1.0,
+        neighborhood: None,
+        wrap: Wrap::default(),
+        portals: Vec::new(),
+        start: None,
+        ends: Vec::new(),
+        json: false,
+    };
+    let mut passes = Vec::new();
+
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            "--json" => {
+                options.json = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{flag} expects a value"))?;
+        match flag.as_str() {
+            "--map" => options.map = Some(value),
+            "--width" => options.width = parse_value(&flag, &value)?,
+            "--height" => options.height = parse_value(&flag, &value)?,
+            "--generator" => {
+                options.generator = generators(0.0)
+                    .iter()
+                    .position(|generator| slug(generator.name()) == slug(&value))
+                    .ok_or_else(|| format!("unknown generator '{value}'"))?
+            }
+            "--seed" => options.seed = parse_value(&flag, &value)?,
+            "--wall-chance" => options.cave.wall_change = parse_value(&flag, &value)?,
+            "--smoothing" => {
+                let count = parse_value(&flag, &value)?;
+                options.cave.passes = vec![SmoothingPass::default(); count];
+            }
+            "--pass" => passes.push(parse_pass(&flag, &value)?),
+            "--braid" => options.braid = parse_value(&flag, &value)?,
+            "--algorithm" => {
+                options.algorithm = find_by_name(AlgorithmType::all(), AlgorithmType::name, &value)
+                    .ok_or_else(|| format!("unknown algorithm '{value}'"))?
+            }
+            "--heuristic" => {
+                options.heuristic = Some(
+                    find_by_name(&heuristics(), Heuristic::name, &value)
+                        .ok_or_else(|| format!("unknown heuristic '{value}'"))?,
+                )
+            }
+            "--weight" => options.weight = parse_value(&flag, &value)?,
+            "--neighborhood" => {
+                options.neighborhood = Some(
+                    find_by_name(Neighborhood::all(), Neighborhood::name, &value)
+                        .ok_or_else(|| format!("unknown neighborhood '{value}'"))?,
+                )
+            }
+            "--wrap" => {
+                options.wrap = find_by_name(Wrap::all(), Wrap::name, &value)
+                    .ok_or_else(|| format!("unknown wrap '{value}'"))?
+            }
+            "--portal" => options.portals.push(parse_portal(&flag, &value)?),
+            "--start" => options.start = Some(parse_position(&flag, &value)?),
+            "--end" => options.ends.push(parse_position(&flag, &value)?),
+            _ => return Err(format!("unknown option '{flag}'")),
+        }
+    }
+
+    if !passes.is_empty() {
+        options.cave.passes = passes;
+    }
+    if options.width < 3 || options.height < 3 {
+        return Err("the generated map must be at least 3x3".to_owned());
+    }
+    Ok(options)
+}
+
+fn load_grid(options: &Options) -> Result<Grid, String> {
+    let Some(path) = &options.map else {
+        // The cave comes first, swap in the one with the parsed settings
+        let mut generators = generators(options.braid);
+        generators[0] = Box::new(options.cave.clone());
+        let generator = &mut generators[options.generator];
+        generator.set_seed(options.seed);
+
+        let mut grid = Grid::new(options.width, options.height);
+        grid.neighborhood = options.neighborhood.unwrap_or_default();
+        generator.generate(&mut grid);
+        return Ok(grid);
+    };
+
+    let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    let grid = if path.ends_with(".map") {
+        movingai::parse_map(&text)
+    } else {
+        map_file::from_text(&text)
+    };
+    grid.map_err(|err| format!("{path}: {err}"))
+}
+
+struct SearchResult {
+    start: (usize, usize),
+    end: (usize, usize),
+    path: Vec<(usize, usize)>,
+    stats: SearchStats,
+    time_ms: f64,
+}
+
+fn print_text(options: &Options, grid: &Grid, heuristic: &Heuristic, result: &SearchResult) {
+    println!("algorithm:    {}", options.algorithm.name());
+    println!(
+        "heuristic:    {} (weight {})",
+        heuristic.name(),
+        options.weight
+    );
+    println!(
+        "grid:         {}x{}, {}, wrap {}, {} portals",
+        grid.width,
+        grid.height,
+        grid.neighborhood.name(),
+        grid.wrap.name().to_lowercase(),
+        grid.portals().len()
+    );
+    println!("start:        {:?}", result.start);
+    println!("end:          {:?}", result.end);
+    println!("expanded:     {}", result.stats.nodes_expanded);
+    println!("pushed:       {}", result.stats.nodes_pushed);
+    println!("max frontier: {}", result.stats.max_frontier);
+    println!("duplicates:   {}", result.stats.duplicate_pops);
+    println!("steps:        {}", result.stats.steps);
+    println!("time:         {:.3} ms", result.time_ms);
+    match result.stats.path_cost {
+        Some(cost) => {
+            println!("path cost:    {cost}");
+            println!("path length:  {}", result.path.len());
+            let cells: Vec<String> = result
+                .path
+                .iter()
+                .map(|(x, y)| format!("({x}, {y})"))
+                .collect();
+            println!("path:         {}", cells.join(" "));
+        }
+        None => println!("path:         none"),
+    }
+}
+
+fn print_json(options: &Options, grid: &Grid, heuristic: &Heuristic, result: &SearchResult) {
+    let path: Vec<String> = result
+        .path
+        .iter()
+        .map(|(x, y)| format!("[{x},{y}]"))
+        .collect();
+    let stats = &result.stats;
+    let cost = stats
+        .path_cost
+        .map_or_else(|| "null".to_owned(), |cost| cost.to_string());
+
+    println!(
+        "{{\"algorithm\":\"{}\",\"heuristic\":\"{}\",\"weight\":{},\"width\":{},\"height\":{},\
+         \"neighborhood\":\"{}\",\"start\":[{},{}],\"end\":[{},{}],\"found\":{},\"cost\":{},\
+         \"expanded\":{},\"pushed\":{},\"max_frontier\":{},\"duplicate_pops\":{},\"steps\":{},\
+         \"time_ms\":{:.3},\"path\":[{}]}}",
+        options.algorithm.name(),
+        heuristic.name(),
+        options.weight,
+        grid.width,
+        grid.height,
+        grid.neighborhood.name(),
+        result.start.0,
+        result.start.1,
+        result.end.0,
+        result.end.1,
+        stats.path_cost.is_some(),
+        cost,
+        stats.nodes_expanded,
+        stats.nodes_pushed,
+        stats.max_frontier,
+        stats.duplicate_pops,
+        stats.steps,
+        result.time_ms,
+        path.join(",")
+    );
+}
+
+fn run(options: &Options) -> Result<(Grid, Heuristic, SearchResult), String> {
+    search(options, load_grid(options)?)
+}
+
+/// Applies the options to a loaded grid and runs the search on it
+fn search(options: &Options, mut grid: Grid) -> Result<(Grid, Heuristic, SearchResult), String> {
+    if let Some(neighborhood) = options.neighborhood {
+        grid.neighborhood = neighborhood;
+    }
+    grid.wrap = options.wrap;
+    if grid.effective_wrap() != grid.wrap {
+        return Err(format!(
+            "--wrap {}: hex grids only wrap an even number of offset rows (pointy) or columns (flat)",
+            grid.wrap.name().to_lowercase()
+        ));
+    }
+    for portal in &options.portals {
+        grid.add_portal(portal.a, portal.b, portal.cost)
+            .map_err(|err| format!("--portal: {err}"))?;
+    }
+
+    let start = match options.start {
+        Some(start) => start,
+        None => grid
+            .start()
+            .map_err(|err| format!("{err}, pass --start X,Y"))?,
+    };
+    let goals = match options.ends.as_slice() {
+        [] => grid.find_all(Cell::End),
+        ends => ends.to_vec(),
+    };
+    if goals.is_empty() {
+        return Err(format!("{}, pass --end X,Y", PathError::NoEnd));
+    }
+    let heuristic = options
+        .heuristic
+        .clone()
+        .unwrap_or_else(|| Heuristic::for_neighborhood(grid.neighborhood));
+
+    let mut algorithm = try_create_algorithm_to_nearest(
+        options.algorithm,
+        &grid,
+        start,
+        &goals,
+        &heuristic,
+        options.weight,
+    )
+    .map_err(|err| err.to_string())?;
+    let timer = Instant::now();
+    while algorithm.step(&grid) {}
+    let time_ms = timer.elapsed().as_secs_f64() * 1000.0;
+
+    // The goal that was reached, or the first one if none was
+    let path = algorithm.get_path();
+    let end = path.last().copied().filter(|_| algorithm.found_path());
+    let result = SearchResult {
+        start,
+        end: end.unwrap_or(goals[0]),
+        path,
+        stats: algorithm.stats(),
+        time_ms,
+    };
+    Ok((grid, heuristic, result))
+}
+
+fn main() {
+    let outcome = parse_args(env::args().skip(1))
+        .and_then(|options| run(&options).map(|outcome| (options, outcome)));
+    let (options, (grid, heuristic, result)) = match outcome {
+        Ok(outcome) => outcome,
+        Err(message) => {
+            eprintln!("pathfind-cli: {message}");
+            eprintln!("Try 'pathfind-cli --help' for more information.");
+            process::exit(2);
+        }
+    };
+
+    if options.json {
+        print_json(&options, &grid, &heuristic, &result);
+    } else {
+        print_text(&options, &grid, &heuristic, &result);
+    }
+
+    if result.stats.path_cost.is_none() {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Options {
+        parse_args(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    fn search_error(args: &[&str], map: &str) -> String {
+        let grid = map_file::from_text(map).unwrap();
+        search(&options(args), grid).err().unwrap()
+    }
+
+    #[test]
+    fn test_endpoint_errors() {
+        let map = "S..\n.#.\n..E\n";
+        assert_eq!(
+            search_error(&[], "S..\n.#.\nS.E\n"),
+            "grid has more than one start, pass --start X,Y"
+        );
+        assert_eq!(
+            search_error(&[], "...\n.#.\n..E\n"),
+            "grid has no start, pass --start X,Y"
+        );
+        assert_eq!(
+            search_error(&[], "S..\n.#.\n...\n"),
+            "grid has no end, pass --end X,Y"
+        );
+        assert_eq!(search_error(&["--end", "1,1"], map), "end is inside a wall");
+        assert_eq!(
+            search_error(&["--start", "5,0"], map),
+            "(5, 0) is outside the grid"
+        );
+        assert_eq!(
+            search_error(&["--algorithm", "jps"], map),
+            "JPS can't search this grid's moves or terrain"
+        );
+        assert_eq!(
+            search_error(&["--neighborhood", "hex-flat-top", "--wrap", "torus"], map),
+            "--wrap torus: hex grids only wrap an even number of offset rows (pointy) or columns (flat)"
+        );
+    }
+
+    #[test]
+    fn test_nearest_end() {
+        let grid = map_file::from_text("S..\n.#.\n..E\n").unwrap();
+        let (_, _, result) = search(&options(&["--end", "2,2", "--end", "2,0"]), grid).unwrap();
+        assert_eq!(result.end, (2, 0));
+        assert_eq!(result.stats.path_cost, Some(2 * STRAIGHT_COST));
+    }
+}
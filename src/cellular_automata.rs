@@ -1,12 +1,188 @@
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 
-use crate::grid::{Cell, Grid};
+use crate::grid::{Cell, Grid, Neighborhood, Terrain};
+use crate::hex;
+use crate::map_generator::{GenerationSteps, MapGenerator};
 use rand::prelude::*;
 use rand::rngs::SmallRng;
 
+/// Birth/survival rule in B/S notation with walls as the live cells, such as
+/// `B5678/S345678`: a floor cell with 5 to 8 neighboring walls becomes a
+/// wall, a wall with 3 to 8 stays one.
+///
+/// Counts above 9, possible with a radius over 1, are written as a comma
+/// separated list that may contain ranges, such as `B13-24/S12,14-24`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub birth: Vec<u32>,
+    pub survival: Vec<u32>,
+}
+
+impl Rule {
+    /// Whether a cell is a wall after the pass, given how many of its
+    /// neighbors were walls before it
+    pub fn next(&self, is_wall: bool, wall_neighbors: u32) -> bool {
+        let counts = if is_wall { &self.survival } else { &self.birth };
+        counts.contains(&wall_neighbors)
+    }
+}
+
+impl Rule {
+    /// The rule that smooths caves best for the neighborhood: `default` on
+    /// square grids, `B456/S23456` on hex grids, which makes a wall of every
+    /// hex with 4 or more walls among itself and its 6 neighbors and floor
+    /// of every hex with 2 or fewer
+    pub fn for_neighborhood(neighborhood: Neighborhood) -> Self {
+        match neighborhood {
+            Neighborhood::Hex(_) => Self {
+                birth: vec![4, 5, 6],
+                survival: vec![2, 3, 4, 5, 6],
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// The classic cave rule: 5 or more walls in the 3x3 block around a cell,
+/// the cell included, make it a wall, fewer than 4 make it floor
+impl Default for Rule {
+    fn default() -> Self {
+        Self {
+            birth: vec![5, 6, 7, 8],
+            survival: vec![3, 4, 5, 6, 7, 8],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleError {
+    /// Not of the form `B<counts>/S<counts>`
+    Format(String),
+    InvalidCount(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Format(rule) => write!(f, "expected B<counts>/S<counts>, got '{rule}'"),
+            Self::InvalidCount(count) => write!(f, "'{count}' is not a neighbor count"),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+fn parse_counts(text: &str) -> Result<Vec<u32>, RuleError> {
+    let invalid = |count: &str| RuleError::InvalidCount(count.to_owned());
+    let mut counts = Vec::new();
+    if !text.contains([',', '-']) {
+        for c in text.chars() {
+            counts.push(c.to_digit(10).ok_or_else(|| invalid(&c.to_string()))?);
+        }
+    } else {
+        for item in text.split(',') {
+            let item = item.trim();
+            let (low, high) = item.split_once('-').unwrap_or((item, item));
+            let low: u32 = low.trim().parse().map_err(|_| invalid(item))?;
+            let high: u32 = high.trim().parse().map_err(|_| invalid(item))?;
+            if low > high {
+                return Err(invalid(item));
+            }
+            counts.extend(low..=high);
+        }
+    }
+    counts.sort_unstable();
+    counts.dedup();
+    Ok(counts)
+}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format = || RuleError::Format(s.to_owned());
+        let (birth, survival) = s.trim().split_once('/').ok_or_else(format)?;
+        let strip = |part: &str, prefix: char| {
+            part.strip_prefix(prefix)
+                .or_else(|| part.strip_prefix(prefix.to_ascii_lowercase()))
+                .map(str::to_owned)
+        };
+        let birth = strip(birth, 'B').ok_or_else(format)?;
+        let survival = strip(survival, 'S').ok_or_else(format)?;
+        Ok(Self {
+            birth: parse_counts(&birth)?,
+            survival: parse_counts(&survival)?,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = |counts: &[u32]| {
+            let items: Vec<String> = counts.iter().map(u32::to_string).collect();
+            if counts.iter().all(|&count| count < 10) {
+                items.concat()
+            } else {
+                items.join(",")
+            }
+        };
+        write!(f, "B{}/S{}", counts(&self.birth), counts(&self.survival))
+    }
+}
+
+/// One smoothing pass: the rule and how many cells out from a cell its
+/// neighbors are counted, 1 for the 8 surrounding cells or the 6 around a hex
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SmoothingPass {
+    /// `None` uses `Rule::for_neighborhood` of the grid
+    pub rule: Option<Rule>,
+    pub radius: usize,
+    /// Apply the rule to all cells at once rather than row by row, see
+    /// `CellularAutomata::smooth`
+    pub double_buffered: bool,
+}
+
+impl SmoothingPass {
+    pub fn rule_for(&self, neighborhood: Neighborhood) -> Rule {
+        self.rule
+            .clone()
+            .unwrap_or_else(|| Rule::for_neighborhood(neighborhood))
+    }
+}
+
+impl Default for SmoothingPass {
+    fn default() -> Self {
+        Self {
+            rule: None,
+            radius: 1,
+            double_buffered: false,
+        }
+    }
+}
+
+impl fmt::Display for SmoothingPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.rule {
+            Some(rule) => write!(f, "{rule}")?,
+            None => write!(f, "default rule")?,
+        }
+        if self.radius != 1 {
+            write!(f, " r{}", self.radius)?;
+        }
+        if self.double_buffered {
+            write!(f, " double-buffered")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct CellularAutomata {
     pub wall_change: f32,
-    pub smoothing_passes: u32,
+    /// Run in order, after the random fill
+    pub passes: Vec<SmoothingPass>,
     pub seed: u64,
 }
 
@@ -14,35 +190,187 @@ impl Default for CellularAutomata {
     fn default() -> Self {
         Self {
             wall_change: 0.45,
-            smoothing_passes: 1,
+            passes: vec![SmoothingPass::default()],
             seed: 12345,
         }
     }
 }
 
-impl CellularAutomata {
-    pub fn generate(&self, grid: &mut Grid) {
-        let mut rng = SmallRng::seed_from_u64(self.seed);
+impl MapGenerator for CellularAutomata {
+    fn generate(&self, grid: &mut Grid) {
+        let mut steps = CaveSteps::new(self.clone());
+        while steps.step(grid) {}
+    }
 
-        // phase 1: random fill
-        self.random_fill(grid, &mut rng);
+    fn steps(&self) -> Option<Box<dyn GenerationSteps>> {
+        Some(Box::new(CaveSteps::new(self.clone())))
+    }
 
-        // phase 2: smooth
-        for _ in 0..self.smoothing_passes {
-            self.smooth(grid);
+    fn name(&self) -> &'static str {
+        "Cave"
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn describe(&self) -> String {
+        let passes: Vec<String> = self.passes.iter().map(SmoothingPass::to_string).collect();
+        if passes.is_empty() {
+            format!("{:.0}% walls, no smoothing", self.wall_change * 100.0)
+        } else {
+            format!(
+                "{:.0}% walls, {}",
+                self.wall_change * 100.0,
+                passes.join(", ")
+            )
         }
+    }
+}
 
-        // phase 3: keep only largest connected region or connect regions
-        //self.keep_largest_region(grid);
-        self.connect_regions(grid);
+/// Phases of a cave generation, in the order they run
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CavePhase {
+    RandomFill,
+    /// Index into `CellularAutomata::passes`
+    Smoothing(usize),
+    /// Finding the floor regions that corridors will connect
+    Regions,
+    /// One corridor is carved per step
+    Corridor(usize),
+    Endpoints,
+}
+
+/// Cave generation one `CavePhase` at a time, so that the app can show
+/// each phase. `CellularAutomata::generate` runs all of them at once
+pub struct CaveSteps {
+    settings: CellularAutomata,
+    rng: SmallRng,
+    next: Option<CavePhase>,
+    last: Option<CavePhase>,
+    /// Region of every floor cell, filled by `CavePhase::Regions`
+    regions: HashMap<(usize, usize), usize>,
+    region_count: usize,
+    corridors: Vec<((usize, usize), (usize, usize))>,
+}
 
-        // phase 4: set endpoints
-        self.place_endpoints(grid, &mut rng);
+impl CaveSteps {
+    pub fn new(settings: CellularAutomata) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(settings.seed),
+            settings,
+            next: Some(CavePhase::RandomFill),
+            last: None,
+            regions: HashMap::new(),
+            region_count: 0,
+            corridors: Vec::new(),
+        }
     }
 
+    /// The phase the last `step` ran
+    pub fn last_phase(&self) -> Option<CavePhase> {
+        self.last
+    }
+
+    fn after_regions(&self) -> CavePhase {
+        if self.corridors.is_empty() {
+            CavePhase::Endpoints
+        } else {
+            CavePhase::Corridor(0)
+        }
+    }
+}
+
+impl GenerationSteps for CaveSteps {
+    fn step(&mut self, grid: &mut Grid) -> bool {
+        let Some(phase) = self.next else {
+            return false;
+        };
+        let settings = &self.settings;
+
+        self.next = match phase {
+            CavePhase::RandomFill => {
+                settings.random_fill(grid, &mut self.rng);
+                if settings.passes.is_empty() {
+                    Some(CavePhase::Regions)
+                } else {
+                    Some(CavePhase::Smoothing(0))
+                }
+            }
+            CavePhase::Smoothing(idx) => {
+                settings.smooth(grid, &settings.passes[idx]);
+                if idx + 1 < settings.passes.len() {
+                    Some(CavePhase::Smoothing(idx + 1))
+                } else {
+                    Some(CavePhase::Regions)
+                }
+            }
+            CavePhase::Regions => {
+                let regions = settings.find_regions(grid);
+                self.corridors = settings.corridors(&regions);
+                self.region_count = regions.len();
+                self.regions = regions
+                    .iter()
+                    .flat_map(|(&id, cells)| cells.iter().map(move |&pos| (pos, id as usize)))
+                    .collect();
+                Some(self.after_regions())
+            }
+            CavePhase::Corridor(idx) => {
+                let (from, to) = self.corridors[idx];
+                settings.carve_corridor(grid, from, to);
+                if idx + 1 < self.corridors.len() {
+                    Some(CavePhase::Corridor(idx + 1))
+                } else {
+                    Some(CavePhase::Endpoints)
+                }
+            }
+            CavePhase::Endpoints => {
+                settings.place_endpoints(grid, &mut self.rng);
+                self.regions.clear();
+                None
+            }
+        };
+        self.last = Some(phase);
+        self.next.is_some()
+    }
+
+    fn phase(&self) -> String {
+        match self.last {
+            None => "Not started".to_owned(),
+            Some(CavePhase::RandomFill) => format!(
+                "Random fill, {:.0}% walls",
+                self.settings.wall_change * 100.0
+            ),
+            Some(CavePhase::Smoothing(idx)) => format!(
+                "Smoothing pass {} of {}, {}",
+                idx + 1,
+                self.settings.passes.len(),
+                self.settings.passes[idx]
+            ),
+            Some(CavePhase::Regions) => format!("Found {} regions", self.region_count),
+            Some(CavePhase::Corridor(idx)) => {
+                format!("Corridor {} of {}", idx + 1, self.corridors.len())
+            }
+            Some(CavePhase::Endpoints) => "Placed start and end".to_owned(),
+        }
+    }
+
+    fn group(&self, x: usize, y: usize) -> Option<usize> {
+        self.regions.get(&(x, y)).copied()
+    }
+}
+
+impl CellularAutomata {
     fn random_fill(&self, grid: &mut Grid, rng: &mut SmallRng) {
+        grid.clear_portals();
         for y in 0..grid.height {
             for x in 0..grid.width {
+                grid.set_terrain(x, y, Terrain::default());
+                grid.set_one_way(x, y, None);
                 let is_border = x == 0 || y == 0 || x == grid.width - 1 || y == grid.height - 1;
                 if is_border {
                     grid.set(x, y, Cell::Wall);
@@ -58,14 +386,29 @@ impl CellularAutomata {
         }
     }
 
-    fn count_wall_neighbors(&self, grid: &Grid, x: usize, y: usize) -> u32 {
+    /// Walls in the square of the given radius around a cell, or within
+    /// `radius` moves on hex grids, the cell itself included. Cells outside
+    /// the grid count as walls
+    fn count_wall_neighbors(&self, grid: &Grid, x: usize, y: usize, radius: usize) -> u32 {
+        let hex = grid.neighborhood.hex_orientation();
+        let radius = radius as i32;
         let mut count = 0;
 
-        for dy in -1i32..=1 {
-            for dx in -1i32..=1 {
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
                 let nx = x as i32 + dx;
                 let ny = y as i32 + dy;
 
+                if let Some(orientation) = hex {
+                    let axial = |x: i32, y: i32| {
+                        hex::offset_to_axial(orientation, (x as isize, y as isize))
+                    };
+                    if hex::axial_distance(axial(x as i32, y as i32), axial(nx, ny)) > radius as u32
+                    {
+                        continue;
+                    }
+                }
+
                 // out of bounds or actual wall
                 if nx < 0
                     || ny < 0
@@ -80,18 +423,28 @@ impl CellularAutomata {
         count
     }
 
-    fn smooth(&self, grid: &mut Grid) {
+    /// Apply the rule to every cell but the border, row by row in place, so
+    /// cells already smoothed count towards the ones after them. Double-buffered
+    /// passes apply it to every cell at once instead
+    fn smooth(&self, grid: &mut Grid, pass: &SmoothingPass) {
+        let rule = pass.rule_for(grid.neighborhood);
+        let mut walls = Vec::new();
         for y in 1..grid.height - 1 {
             for x in 1..grid.width - 1 {
-                let wall_count = self.count_wall_neighbors(grid, x, y);
-
-                if wall_count >= 5 {
-                    grid.set(x, y, Cell::Wall);
-                } else if wall_count < 4 {
-                    grid.set(x, y, Cell::Empty);
+                let is_wall = grid.get(x, y) == Some(Cell::Wall);
+                let count = self.count_wall_neighbors(grid, x, y, pass.radius);
+                let wall = rule.next(is_wall, count - u32::from(is_wall));
+                if pass.double_buffered {
+                    walls.push(((x, y), wall));
+                } else {
+                    grid.set(x, y, if wall { Cell::Wall } else { Cell::Empty });
                 }
             }
         }
+
+        for ((x, y), wall) in walls {
+            grid.set(x, y, if wall { Cell::Wall } else { Cell::Empty });
+        }
     }
 
     fn find_regions(&self, grid: &Grid) -> HashMap<u32, HashSet<(usize, usize)>> {
@@ -140,16 +493,13 @@ impl CellularAutomata {
             visited.insert((x, y));
             region.insert((x, y));
 
-            let neighbors = grid.neighbors(x, y);
-
-            for n in neighbors {
-                queue.push_back(n);
-            }
+            // Regions are 4-connected so corridors connect them for every neighborhood
+            queue.extend(grid.neighbors_in(x, y, Neighborhood::Four).passable());
         }
 
         region
     }
-    
+
     #[allow(dead_code)]
     fn keep_largest_region(&self, grid: &mut Grid) {
         let regions = self.find_regions(grid);
@@ -182,15 +532,11 @@ impl CellularAutomata {
         while let Some((x, y)) = queue.pop_front() {
             furthest = (x, y);
 
-            for (nx, ny) in grid.neighbors(x, y) {
+            for (nx, ny) in grid.neighbors_in(x, y, Neighborhood::Four).passable() {
                 if visited.contains(&(nx, ny)) {
                     continue;
                 }
 
-                if grid.get(nx, ny) != Some(Cell::Empty) {
-                    continue;
-                }
-
                 visited.insert((nx, ny));
                 queue.push_back((nx, ny));
             }
@@ -228,7 +574,7 @@ impl CellularAutomata {
     }
 
     fn region_centroid(&self, region: &HashSet<(usize, usize)>) -> (usize, usize) {
-        if region.len() == 0 {
+        if region.is_empty() {
             panic!("empty region")
         }
         let sum_x: usize = region.iter().map(|p| p.0).sum();
@@ -260,17 +606,23 @@ impl CellularAutomata {
         }
     }
 
-    fn connect_regions(&self, grid: &mut Grid) {
-        let regions = self.find_regions(grid);
-
+    /// Corridors between region centroids that connect every region,
+    /// closest pairs first
+    fn corridors(
+        &self,
+        regions: &HashMap<u32, HashSet<(usize, usize)>>,
+    ) -> Vec<((usize, usize), (usize, usize))> {
+        let mut corridors = Vec::new();
         if regions.len() <= 1 {
-            return;
+            return corridors;
         }
 
-        let centroids: Vec<(u32, (usize, usize))> = regions
+        let mut centroids: Vec<(u32, (usize, usize))> = regions
             .iter()
             .map(|(&id, region)| (id, self.region_centroid(region)))
             .collect();
+        // HashMap order differs between runs, sort so a seed always gives the same map
+        centroids.sort_by_key(|&(id, _)| id);
 
         let mut connected: HashSet<u32> = HashSet::new();
         connected.insert(centroids[0].0);
@@ -299,10 +651,11 @@ impl CellularAutomata {
                 let from_pos = centroids.iter().find(|(id, _)| *id == from_id).unwrap().1;
                 let to_pos = centroids.iter().find(|(id, _)| *id == to_id).unwrap().1;
 
-                self.carve_corridor(grid, from_pos, to_pos);
+                corridors.push((from_pos, to_pos));
                 connected.insert(to_id);
             }
         }
+        corridors
     }
 
     fn distance(&self, a: (usize, usize), b: (usize, usize)) -> f64 {
@@ -324,7 +677,24 @@ mod tests {
 
         grid.set(0, 0, Cell::Wall);
         // Corner (0,0) has 5 out-of-bounds neighbors + itself
-        assert_eq!(gen.count_wall_neighbors(&grid, 0, 0), 6);
+        assert_eq!(gen.count_wall_neighbors(&grid, 0, 0, 1), 6);
+    }
+
+    #[test]
+    fn test_count_neighbors_hex() {
+        let mut grid = Grid::new(5, 5);
+        grid.neighborhood = Neighborhood::Hex(hex::HexOrientation::Pointy);
+        let gen = CellularAutomata::default();
+
+        grid.set(0, 0, Cell::Wall);
+        // 4 of the 6 neighbors are outside the grid
+        assert_eq!(gen.count_wall_neighbors(&grid, 0, 0, 1), 5);
+        assert_eq!(gen.count_wall_neighbors(&grid, 2, 2, 2), 0);
+        grid.set(0, 1, Cell::Wall);
+        grid.set(0, 2, Cell::Wall);
+        // Only (0, 1) borders (1, 1) on pointy-top grids, the square count is 3
+        assert_eq!(gen.count_wall_neighbors(&grid, 1, 1, 1), 1);
+        assert_eq!(gen.count_wall_neighbors(&grid, 2, 2, 2), 2);
     }
 
     #[test]
@@ -334,4 +704,125 @@ mod tests {
             vec![(1, 1), (2, 1), (3, 1), (2, 2)].into_iter().collect();
         assert_eq!(gen.region_centroid(&region), (2, 1));
     }
+
+    #[test]
+    fn test_same_seed_same_map() {
+        for seed in 1..6 {
+            let generate = || {
+                let mut grid = Grid::new(40, 30);
+                CellularAutomata {
+                    seed,
+                    ..Default::default()
+                }
+                .generate(&mut grid);
+                grid.cells
+            };
+            assert_eq!(generate(), generate());
+        }
+    }
+
+    #[test]
+    fn test_default_rule_is_four_five() {
+        let rule = Rule::default();
+        for is_wall in [false, true] {
+            // Walls in the 3x3 block, the cell itself included
+            for neighbors in 0..=8 {
+                let count = neighbors + u32::from(is_wall);
+                let expected = count >= 5 || (count == 4 && is_wall);
+                assert_eq!(rule.next(is_wall, neighbors), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let rule: Rule = "B678/S345678".parse().unwrap();
+        assert_eq!(rule.birth, vec![6, 7, 8]);
+        assert_eq!(rule.survival, vec![3, 4, 5, 6, 7, 8]);
+        assert_eq!(rule.to_string(), "B678/S345678");
+        assert_eq!("b5678/s345678".parse(), Ok(Rule::default()));
+
+        let wide: Rule = "B13-15/S12,20-21".parse().unwrap();
+        assert_eq!(wide.birth, vec![13, 14, 15]);
+        assert_eq!(wide.to_string(), "B13,14,15/S12,20,21");
+        assert_eq!(wide.to_string().parse(), Ok(wide));
+
+        assert_eq!(
+            "S345/B678".parse::<Rule>(),
+            Err(RuleError::Format("S345/B678".to_owned()))
+        );
+        assert_eq!(
+            "B5x/S4".parse::<Rule>(),
+            Err(RuleError::InvalidCount("x".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_smooth_in_place() {
+        let gen = CellularAutomata::default();
+        let mut filled = Grid::new(30, 20);
+        gen.random_fill(&mut filled, &mut SmallRng::seed_from_u64(gen.seed));
+
+        // The classic rule, applied to each cell as the rows are walked
+        let mut expected = filled.clone();
+        for y in 1..19 {
+            for x in 1..29 {
+                match gen.count_wall_neighbors(&expected, x, y, 1) {
+                    5.. => expected.set(x, y, Cell::Wall),
+                    0..=3 => expected.set(x, y, Cell::Empty),
+                    _ => {}
+                }
+            }
+        }
+        let mut grid = filled.clone();
+        gen.smooth(&mut grid, &SmoothingPass::default());
+        assert_eq!(grid.cells, expected.cells);
+
+        let double_buffered = SmoothingPass {
+            double_buffered: true,
+            ..Default::default()
+        };
+        let mut grid = filled;
+        gen.smooth(&mut grid, &double_buffered);
+        assert_ne!(grid.cells, expected.cells);
+    }
+
+    #[test]
+    fn test_steps_match_generate() {
+        let generator = CellularAutomata {
+            seed: 3,
+            passes: vec![
+                SmoothingPass::default(),
+                SmoothingPass {
+                    rule: Some("B13-24/S12-24".parse().unwrap()),
+                    radius: 2,
+                    double_buffered: true,
+                },
+            ],
+            ..Default::default()
+        };
+        let mut expected = Grid::new(40, 30);
+        generator.generate(&mut expected);
+
+        let mut grid = Grid::new(40, 30);
+        let mut steps = CaveSteps::new(generator.clone());
+        let mut phases = Vec::new();
+        while steps.step(&mut grid) {
+            phases.push(steps.last_phase().unwrap());
+        }
+        phases.push(steps.last_phase().unwrap());
+
+        assert_eq!(grid.cells, expected.cells);
+        assert_eq!(
+            phases[..4],
+            [
+                CavePhase::RandomFill,
+                CavePhase::Smoothing(0),
+                CavePhase::Smoothing(1),
+                CavePhase::Regions
+            ]
+        );
+        assert_eq!(phases.last(), Some(&CavePhase::Endpoints));
+        assert!(!steps.step(&mut grid));
+    }
 }
@@ -1,33 +1,47 @@
-use crate::grid::{Cell, Grid};
-use crate::pathfinding::{NodeState, PathfindingAlgorithm};
-use std::collections::{HashMap, HashSet};
-
-pub struct Dfs {
-    stack: Vec<(usize, usize)>,
-    visited: HashSet<(usize, usize)>,
-    parents: HashMap<(usize, usize), (usize, usize)>,
-    node_states: HashMap<(usize, usize), NodeState>,
-    start: (usize, usize),
-    end: (usize, usize),
+use crate::grid::Grid;
+use crate::pathfinding::{
+    EventKind, NodeState, PathfindingAlgorithm, SearchEvent, SearchLog, SearchStats,
+};
+use crate::search_space::{NodeSetStore, NodeStore, SearchSpace};
+
+pub struct Dfs<S: SearchSpace = Grid> {
+    stack: Vec<S::Node>,
+    visited: S::Set,
+    parents: S::Map<S::Node>,
+    log: SearchLog<S>,
+    stats: SearchStats,
+    start: S::Node,
+    goals: Vec<S::Node>,
+    /// The goal the path leads to, the first of `goals` until one is reached
+    end: S::Node,
     finished: bool,
     found_path: bool,
 }
 
-impl Dfs {
-    pub fn new(start: (usize, usize), end: (usize, usize)) -> Self {
+impl<S: SearchSpace> Dfs<S> {
+    pub fn new(space: &S, start: S::Node, end: S::Node) -> Self {
+        Self::to_nearest(space, start, &[end])
+    }
+
+    /// Stops at whichever of `goals` it runs into first
+    pub fn to_nearest(space: &S, start: S::Node, goals: &[S::Node]) -> Self {
         let mut dfs = Dfs {
             stack: Vec::new(),
-            visited: HashSet::new(),
-            parents: HashMap::new(),
-            node_states: HashMap::new(),
+            visited: space.node_set(),
+            parents: space.node_map(),
+            log: SearchLog::new(space),
+            stats: SearchStats::default(),
             start,
-            end,
+            goals: goals.to_vec(),
+            end: goals.first().copied().unwrap_or(start),
             finished: false,
             found_path: false,
         };
 
         dfs.stack.push(start);
-        dfs.node_states.insert(start, NodeState::InQueue);
+        dfs.log
+            .record(dfs.stats.steps, EventKind::Push, start, NodeState::InQueue);
+        dfs.stats.record_push(1);
 
         dfs
     }
@@ -35,19 +49,29 @@ impl Dfs {
     fn mark_path(&mut self) {
         let mut current = self.end;
         while current != self.start {
-            self.node_states.insert(current, NodeState::Path);
-            if let Some(&parent) = self.parents.get(&current) {
+            self.log.record(
+                self.stats.steps,
+                EventKind::PathMark,
+                current,
+                NodeState::Path,
+            );
+            if let Some(parent) = self.parents.get(current) {
                 current = parent;
             } else {
                 break;
             }
         }
-        self.node_states.insert(self.start, NodeState::Path);
+        self.log.record(
+            self.stats.steps,
+            EventKind::PathMark,
+            self.start,
+            NodeState::Path,
+        );
     }
 }
 
-impl PathfindingAlgorithm for Dfs {
-    fn step(&mut self, grid: &Grid) -> bool {
+impl<S: SearchSpace> PathfindingAlgorithm<S> for Dfs<S> {
+    fn step(&mut self, space: &S) -> bool {
         if self.finished {
             return false;
         }
@@ -60,47 +84,51 @@ impl PathfindingAlgorithm for Dfs {
             }
         };
 
-        self.visited.insert(current);
-        self.node_states.insert(current, NodeState::Visited);
-
-        if current == self.end {
+        self.stats.steps += 1;
+        if !self.visited.insert(current) {
+            self.stats.duplicate_pops += 1;
+        }
+        self.log.record(
+            self.stats.steps,
+            EventKind::Pop,
+            current,
+            NodeState::Visited,
+        );
+        self.stats.nodes_expanded += 1;
+
+        if self.goals.contains(&current) {
+            self.end = current;
             self.finished = true;
             self.found_path = true;
+            self.stats.path_cost = Some(space.path_cost(&self.get_path()));
             self.mark_path();
             return false;
         }
 
-        for (nx, ny) in grid.neighbors(current.0, current.1) {
-            if let Some(cell) = grid.get(nx, ny) {
-                if cell == Cell::Wall {
-                    continue;
-                }
-            }
-
-            if self.visited.contains(&(nx, ny)) {
+        for (next, _) in space.successors(current) {
+            if self.visited.contains(next) {
                 continue;
             }
 
             // Add neighbor to stack
             // NOTE: We don't check "already in stack" like BFS does
             // Duplicates are OK as they'll we skipped when popped
-            if !self.parents.contains_key(&(nx, ny)) {
-                self.parents.insert((nx, ny), current);
+            if !self.parents.contains(next) {
+                self.parents.insert(next, current);
             }
-            self.stack.push((nx, ny));
-            self.node_states.insert((nx, ny), NodeState::InQueue);
+            self.stack.push(next);
+            self.stats.record_push(self.stack.len());
+            self.log
+                .record(self.stats.steps, EventKind::Push, next, NodeState::InQueue);
         }
         true
     }
 
-    fn get_node_state(&self, x: usize, y: usize) -> NodeState {
-        *self
-            .node_states
-            .get(&(x, y))
-            .unwrap_or(&NodeState::Unvisited)
+    fn get_node_state(&self, node: S::Node) -> NodeState {
+        self.log.state(node)
     }
 
-    fn get_path(&self) -> Vec<(usize, usize)> {
+    fn get_path(&self) -> Vec<S::Node> {
         if !self.found_path {
             return Vec::new();
         }
@@ -110,7 +138,7 @@ impl PathfindingAlgorithm for Dfs {
 
         while current != self.start {
             path.push(current);
-            if let Some(&parent) = self.parents.get(&current) {
+            if let Some(parent) = self.parents.get(current) {
                 current = parent;
             } else {
                 break;
@@ -132,4 +160,12 @@ impl PathfindingAlgorithm for Dfs {
     fn name(&self) -> &'static str {
         "DFS"
     }
+
+    fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    fn events(&self) -> &[SearchEvent<S::Node>] {
+        self.log.events()
+    }
 }
@@ -1,40 +1,46 @@
-use crate::grid::{Cell, Grid};
-use crate::pathfinding::{NodeState, PathfindingAlgorithm};
+use crate::grid::Grid;
+use crate::pathfinding::{
+    EventKind, NodeState, PathfindingAlgorithm, SearchEvent, SearchLog, SearchStats,
+};
+use crate::search_space::{NodeSetStore, NodeStore, SearchSpace};
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::BinaryHeap;
 
 #[derive(Eq, PartialEq)]
-struct Node {
-    position: (usize, usize),
+struct Node<N> {
+    position: N,
     distance: u32,
 }
 
-impl Ord for Node {
+impl<N: Eq> Ord for Node<N> {
     fn cmp(&self, other: &Self) -> Ordering {
         other.distance.cmp(&self.distance)
     }
 }
 
-impl PartialOrd for Node {
+impl<N: Eq> PartialOrd for Node<N> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-pub struct Dijkstra {
-    pub distances: HashMap<(usize, usize), u32>,
-    pub parents: HashMap<(usize, usize), (usize, usize)>,
-    pub visited: HashSet<(usize, usize)>,
-    pub node_states: HashMap<(usize, usize), NodeState>,
-    queue: BinaryHeap<Node>,
-    start: (usize, usize),
-    end: (usize, usize),
+pub struct Dijkstra<S: SearchSpace = Grid> {
+    pub distances: S::Map<u32>,
+    pub parents: S::Map<S::Node>,
+    pub visited: S::Set,
+    log: SearchLog<S>,
+    queue: BinaryHeap<Node<S::Node>>,
+    stats: SearchStats,
+    start: S::Node,
+    goals: Vec<S::Node>,
+    /// The goal the path leads to, the first of `goals` until one is reached
+    end: S::Node,
     pub finished: bool,
     pub found_path: bool,
 }
 
-impl PathfindingAlgorithm for Dijkstra {
-    fn step(&mut self, grid: &Grid) -> bool {
+impl<S: SearchSpace> PathfindingAlgorithm<S> for Dijkstra<S> {
+    fn step(&mut self, space: &S) -> bool {
         if self.finished {
             return false;
         }
@@ -48,58 +54,62 @@ impl PathfindingAlgorithm for Dijkstra {
         };
 
         let pos = current.position;
+        self.stats.steps += 1;
 
-        if self.visited.contains(&pos) {
+        if self.visited.contains(pos) {
+            self.stats.duplicate_pops += 1;
             return true;
         }
 
         self.visited.insert(pos);
-        self.node_states.insert(pos, NodeState::Visited);
+        self.log
+            .record(self.stats.steps, EventKind::Pop, pos, NodeState::Visited);
+        self.stats.nodes_expanded += 1;
 
-        if pos == self.end {
+        if self.goals.contains(&pos) {
+            self.end = pos;
             self.finished = true;
             self.found_path = true;
+            self.stats.path_cost = Some(current.distance);
             self.mark_path();
             return false;
         }
 
-        let current_dist = *self.distances.get(&pos).unwrap_or(&u32::MAX);
+        let current_dist = self.distances.get(pos).unwrap_or(u32::MAX);
 
-        for (nx, ny) in grid.neighbors(pos.0, pos.1) {
-            if let Some(cell) = grid.get(nx, ny) {
-                if cell == Cell::Wall {
-                    continue;
-                }
-            }
-
-            if self.visited.contains(&(nx, ny)) {
+        for (next, cost) in space.successors(pos) {
+            if self.visited.contains(next) {
                 continue;
             }
 
-            let new_dist = current_dist + 1;
-            let old_dist = *self.distances.get(&(nx, ny)).unwrap_or(&u32::MAX);
+            let new_dist = current_dist + cost;
+            let old_dist = self.distances.get(next).unwrap_or(u32::MAX);
             if new_dist < old_dist {
-                self.distances.insert((nx, ny), new_dist);
-                self.parents.insert((nx, ny), pos);
+                self.distances.insert(next, new_dist);
+                self.parents.insert(next, pos);
                 self.queue.push(Node {
-                    position: (nx, ny),
+                    position: next,
                     distance: new_dist,
                 });
-                self.node_states.insert((nx, ny), NodeState::InQueue);
+                self.stats.record_push(self.queue.len());
+                let kind = if old_dist == u32::MAX {
+                    EventKind::Push
+                } else {
+                    EventKind::Relax
+                };
+                self.log
+                    .record(self.stats.steps, kind, next, NodeState::InQueue);
             }
         }
 
         true
     }
 
-    fn get_node_state(&self, x: usize, y: usize) -> NodeState {
-        *self
-            .node_states
-            .get(&(x, y))
-            .unwrap_or(&NodeState::Unvisited)
+    fn get_node_state(&self, node: S::Node) -> NodeState {
+        self.log.state(node)
     }
 
-    fn get_path(&self) -> Vec<(usize, usize)> {
+    fn get_path(&self) -> Vec<S::Node> {
         if !self.found_path {
             return Vec::new();
         }
@@ -108,7 +118,7 @@ impl PathfindingAlgorithm for Dijkstra {
 
         while current != self.start {
             path.push(current);
-            if let Some(&parent) = self.parents.get(&current) {
+            if let Some(parent) = self.parents.get(current) {
                 current = parent;
             } else {
                 break;
@@ -130,18 +140,33 @@ impl PathfindingAlgorithm for Dijkstra {
     fn name(&self) -> &'static str {
         "Dijkstra"
     }
+
+    fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    fn events(&self) -> &[SearchEvent<S::Node>] {
+        self.log.events()
+    }
 }
 
-impl Dijkstra {
-    pub fn new(start: (usize, usize), end: (usize, usize)) -> Self {
+impl<S: SearchSpace> Dijkstra<S> {
+    pub fn new(space: &S, start: S::Node, end: S::Node) -> Self {
+        Self::to_nearest(space, start, &[end])
+    }
+
+    /// Stops at whichever of `goals` is the cheapest to reach
+    pub fn to_nearest(space: &S, start: S::Node, goals: &[S::Node]) -> Self {
         let mut dijkstra = Dijkstra {
-            distances: HashMap::new(),
-            parents: HashMap::new(),
-            visited: HashSet::new(),
-            node_states: HashMap::new(),
+            distances: space.node_map(),
+            parents: space.node_map(),
+            visited: space.node_set(),
+            log: SearchLog::new(space),
             queue: BinaryHeap::new(),
+            stats: SearchStats::default(),
             start,
-            end,
+            goals: goals.to_vec(),
+            end: goals.first().copied().unwrap_or(start),
             finished: false,
             found_path: false,
         };
@@ -151,20 +176,58 @@ impl Dijkstra {
             position: start,
             distance: 0,
         });
-        dijkstra.node_states.insert(start, NodeState::InQueue);
+        dijkstra.stats.record_push(1);
+        dijkstra.log.record(
+            dijkstra.stats.steps,
+            EventKind::Push,
+            start,
+            NodeState::InQueue,
+        );
         dijkstra
     }
 
     fn mark_path(&mut self) {
         let mut current = self.end;
         while current != self.start {
-            self.node_states.insert(current, NodeState::Path);
-            if let Some(&parent) = self.parents.get(&current) {
+            self.log.record(
+                self.stats.steps,
+                EventKind::PathMark,
+                current,
+                NodeState::Path,
+            );
+            if let Some(parent) = self.parents.get(current) {
                 current = parent;
             } else {
                 break;
             }
         }
-        self.node_states.insert(self.start, NodeState::Path);
+        self.log.record(
+            self.stats.steps,
+            EventKind::PathMark,
+            self.start,
+            NodeState::Path,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Terrain, STRAIGHT_COST};
+
+    #[test]
+    fn test_detours_around_expensive_terrain() {
+        let mut grid = Grid::new(3, 2);
+        grid.set_terrain(1, 0, Terrain::Water);
+
+        let mut dijkstra = Dijkstra::new(&grid, (0, 0), (2, 0));
+        while dijkstra.step(&grid) {}
+
+        assert!(dijkstra.found_path());
+        assert_eq!(
+            dijkstra.get_path(),
+            vec![(0, 0), (0, 1), (1, 1), (2, 1), (2, 0)]
+        );
+        assert_eq!(dijkstra.distances.get((2, 0)).unwrap(), 4 * STRAIGHT_COST);
     }
 }
@@ -0,0 +1,497 @@
+use crate::astar::Heuristic;
+use crate::grid::{Cell, Grid, Portal, Wrap};
+use crate::node_map::NodeMap;
+use crate::pathfinding::{
+    EventKind, NodeState, PathfindingAlgorithm, SearchEvent, SearchLog, SearchStats,
+};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+const INFINITY: u32 = u32::MAX;
+
+type Key = (u32, u32);
+
+#[derive(Eq, PartialEq)]
+struct Node {
+    position: (usize, usize),
+    key: Key,
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.cmp(&self.key)
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// D* Lite (Koenig & Likhachev). Searches backwards from the end so that
+/// after walls or terrain change, only the cells whose distance to the end
+/// is affected are expanded again (see `notify_changes`).
+///
+/// The start never moves here, so the key modifier `k_m` of the original
+/// algorithm is always zero and left out. The heuristic is built for the
+/// wrap and portals at the start, so a search on a grid where they changed
+/// starts over.
+pub struct DStarLite {
+    g: NodeMap<u32>,
+    rhs: NodeMap<u32>,
+    queued: NodeMap<Key>,
+    queue: BinaryHeap<Node>,
+    log: SearchLog,
+    stats: SearchStats,
+    path: Vec<(usize, usize)>,
+    heuristic: Heuristic,
+    wrap: Wrap,
+    portals: Vec<Portal>,
+    start: (usize, usize),
+    goals: Vec<(usize, usize)>,
+    finished: bool,
+    found_path: bool,
+}
+
+impl DStarLite {
+    pub fn new(
+        grid: &Grid,
+        start: (usize, usize),
+        end: (usize, usize),
+        heuristic: Heuristic,
+    ) -> Self {
+        Self::to_nearest(grid, start, &[end], heuristic)
+    }
+
+    /// Leads to whichever of `goals` is the cheapest to reach, all of them
+    /// being where the backwards search starts from
+    pub fn to_nearest(
+        grid: &Grid,
+        start: (usize, usize),
+        goals: &[(usize, usize)],
+        heuristic: Heuristic,
+    ) -> Self {
+        let mut dstar = DStarLite {
+            g: NodeMap::for_grid(grid),
+            rhs: NodeMap::for_grid(grid),
+            queued: NodeMap::for_grid(grid),
+            queue: BinaryHeap::new(),
+            log: SearchLog::new(grid),
+            stats: SearchStats::default(),
+            path: Vec::new(),
+            heuristic: heuristic.on_grid(grid),
+            wrap: grid.effective_wrap(),
+            portals: grid.portals().to_vec(),
+            start,
+            goals: goals.to_vec(),
+            finished: false,
+            found_path: false,
+        };
+
+        for &goal in goals {
+            dstar.rhs.insert(goal, 0);
+            let key = dstar.key(goal);
+            dstar.enqueue(goal, key);
+        }
+        dstar
+    }
+
+    fn g(&self, pos: (usize, usize)) -> u32 {
+        self.g.get(pos).unwrap_or(INFINITY)
+    }
+
+    fn rhs(&self, pos: (usize, usize)) -> u32 {
+        self.rhs.get(pos).unwrap_or(INFINITY)
+    }
+
+    fn key(&self, pos: (usize, usize)) -> Key {
+        let best = self.g(pos).min(self.rhs(pos));
+        let h = self.heuristic.estimate(self.start, pos);
+        (best.saturating_add(h), best)
+    }
+
+    fn enqueue(&mut self, pos: (usize, usize), key: Key) {
+        let kind = match self.queued.insert(pos, key) {
+            Some(_) => EventKind::Relax,
+            None => EventKind::Push,
+        };
+        self.queue.push(Node { position: pos, key });
+        self.stats.record_push(self.queue.len());
+        self.log
+            .record(self.stats.steps, kind, pos, NodeState::InQueue);
+    }
+
+    fn edge_cost(grid: &Grid, from: (usize, usize), to: (usize, usize)) -> u32 {
+        if !grid.is_passable(from.0, from.1) || !grid.is_passable(to.0, to.1) {
+            INFINITY
+        } else {
+            grid.move_cost(from, to)
+        }
+    }
+
+    /// Recompute the one-step lookahead of `pos` and queue it if inconsistent
+    fn update_vertex(&mut self, grid: &Grid, pos: (usize, usize)) {
+        if !self.goals.contains(&pos) {
+            let rhs = grid
+                .neighbors(pos.0, pos.1)
+                .passable()
+                .map(|next| Self::edge_cost(grid, pos, next).saturating_add(self.g(next)))
+                .min()
+                .unwrap_or(INFINITY);
+            self.rhs.insert(pos, rhs);
+        }
+
+        if self.g(pos) != self.rhs(pos) {
+            let key = self.key(pos);
+            self.enqueue(pos, key);
+        } else {
+            self.queued.remove(pos);
+        }
+    }
+
+    /// Drop queue entries that were removed or re-keyed since they were pushed
+    fn prune(&mut self) {
+        while let Some(top) = self.queue.peek() {
+            if self.queued.get(top.position) == Some(top.key) {
+                break;
+            }
+            self.queue.pop();
+            self.stats.duplicate_pops += 1;
+        }
+    }
+
+    /// Walk from the start to a goal, always to the neighbor closest to one
+    fn extract_path(&self, grid: &Grid) -> Vec<(usize, usize)> {
+        let mut path = vec![self.start];
+        let mut current = self.start;
+
+        while !self.goals.contains(&current) && path.len() <= grid.width * grid.height {
+            let next = grid
+                .neighbors(current.0, current.1)
+                .passable()
+                .min_by_key(|&next| {
+                    Self::edge_cost(grid, current, next).saturating_add(self.g(next))
+                });
+            match next {
+                Some(next) if self.g(next) != INFINITY => {
+                    path.push(next);
+                    current = next;
+                }
+                _ => return Vec::new(),
+            }
+        }
+        path
+    }
+
+    fn mark_path(&mut self) {
+        for &pos in &self.path {
+            self.log
+                .record(self.stats.steps, EventKind::PathMark, pos, NodeState::Path);
+        }
+    }
+}
+
+impl PathfindingAlgorithm for DStarLite {
+    fn step(&mut self, grid: &Grid) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        self.prune();
+        let start_key = self.key(self.start);
+        let consistent = self.g(self.start) == self.rhs(self.start);
+
+        let current = match self.queue.peek() {
+            Some(top) if top.key < start_key || !consistent => self.queue.pop().unwrap(),
+            _ => {
+                self.finished = true;
+                self.found_path = self.g(self.start) != INFINITY;
+                if self.found_path {
+                    self.path = self.extract_path(grid);
+                    self.stats.path_cost = Some(grid.path_cost(&self.path));
+                    self.mark_path();
+                }
+                return false;
+            }
+        };
+
+        let pos = current.position;
+        self.queued.remove(pos);
+        let new_key = self.key(pos);
+        self.stats.steps += 1;
+
+        if current.key < new_key {
+            self.enqueue(pos, new_key);
+            return true;
+        }
+
+        self.stats.nodes_expanded += 1;
+        if self.g(pos) > self.rhs(pos) {
+            self.g.insert(pos, self.rhs(pos));
+            self.log
+                .record(self.stats.steps, EventKind::Pop, pos, NodeState::Visited);
+            for prev in grid.predecessors(pos.0, pos.1) {
+                self.update_vertex(grid, prev);
+            }
+        } else {
+            self.g.insert(pos, INFINITY);
+            self.log
+                .record(self.stats.steps, EventKind::Pop, pos, NodeState::Visited);
+            self.update_vertex(grid, pos);
+            for prev in grid.predecessors(pos.0, pos.1) {
+                self.update_vertex(grid, prev);
+            }
+        }
+
+        true
+    }
+
+    fn get_node_state(&self, pos: (usize, usize)) -> NodeState {
+        self.log.state(pos)
+    }
+
+    fn get_path(&self) -> Vec<(usize, usize)> {
+        if !self.found_path {
+            return Vec::new();
+        }
+        self.path.clone()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn found_path(&self) -> bool {
+        self.found_path
+    }
+
+    fn name(&self) -> &'static str {
+        "D* Lite"
+    }
+
+    fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    fn events(&self) -> &[SearchEvent] {
+        self.log.events()
+    }
+
+    fn notify_changes(&mut self, grid: &Grid, changes: &[(usize, usize)]) -> bool {
+        // The start and goals are fixed, a search towards other ones starts over
+        let moves_endpoint = changes.iter().any(|&pos| {
+            pos == self.start
+                || self.goals.contains(&pos)
+                || matches!(grid.get(pos.0, pos.1), Some(Cell::Start | Cell::End))
+        });
+        if moves_endpoint {
+            return false;
+        }
+        // So does one on different borders or portals than the heuristic's
+        if grid.effective_wrap() != self.wrap || grid.portals() != self.portals {
+            return false;
+        }
+
+        // A changed cell alters the edges into and out of it, and with corner
+        // cutting rules or knight moves also edges passing by it. All of them
+        // start within two cells of the change, counted across wrapped
+        // borders, or at the far end of a portal.
+        for &(x, y) in changes {
+            let mut touched = HashSet::new();
+            for dy in -2..=2 {
+                for dx in -2..=2 {
+                    touched.extend(grid.wrap_position(x as isize + dx, y as isize + dy));
+                }
+            }
+            touched.extend(grid.portal_at((x, y)).and_then(|p| p.other_end((x, y))));
+            for pos in touched {
+                self.update_vertex(grid, pos);
+            }
+        }
+
+        // Only show and replay the cells touched by the repair
+        self.log.restart(self.stats.steps, NodeState::InQueue);
+        self.path.clear();
+        self.stats.path_cost = None;
+        self.finished = false;
+        self.found_path = false;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cellular_automata::CellularAutomata;
+    use crate::dijkstra::Dijkstra;
+    use crate::grid::{Neighborhood, Wrap};
+    use crate::map_generator::MapGenerator;
+
+    fn dijkstra_cost(grid: &Grid, start: (usize, usize), end: (usize, usize)) -> Option<u32> {
+        let mut dijkstra = Dijkstra::new(grid, start, end);
+        while dijkstra.step(grid) {}
+        dijkstra.distances.get(end)
+    }
+
+    fn run(dstar: &mut DStarLite, grid: &Grid) -> usize {
+        let mut steps = 0;
+        while dstar.step(grid) {
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn test_matches_dijkstra() {
+        for seed in 1..4 {
+            let mut grid = Grid::new(40, 30);
+            CellularAutomata {
+                seed,
+                ..Default::default()
+            }
+            .generate(&mut grid);
+            grid.neighborhood = Neighborhood::EightNoCornerCutting;
+            let [start, end] = grid.endpoints().unwrap();
+
+            let mut dstar = DStarLite::new(&grid, start, end, Heuristic::Octile);
+            run(&mut dstar, &grid);
+
+            let path = dstar.get_path();
+            assert_eq!(path.first(), Some(&start));
+            assert_eq!(path.last(), Some(&end));
+            assert_eq!(
+                Some(grid.path_cost(&path)),
+                dijkstra_cost(&grid, start, end)
+            );
+        }
+    }
+
+    #[test]
+    fn test_repairs_after_walls_change() {
+        let mut grid = Grid::new(30, 30);
+        CellularAutomata {
+            seed: 7,
+            ..Default::default()
+        }
+        .generate(&mut grid);
+        grid.take_changes();
+        let [start, end] = grid.endpoints().unwrap();
+
+        let mut dstar = DStarLite::new(&grid, start, end, Heuristic::Manhattan);
+        let initial_steps = run(&mut dstar, &grid);
+
+        // Block the middle of the current path
+        let path = dstar.get_path();
+        let (x, y) = path[path.len() / 2];
+        grid.set(x, y, Cell::Wall);
+        let changes = grid.take_changes();
+
+        assert!(dstar.notify_changes(&grid, &changes));
+        assert!(!dstar.is_finished());
+        let repair_steps = run(&mut dstar, &grid);
+
+        let expected = dijkstra_cost(&grid, start, end);
+        assert_eq!(dstar.found_path(), expected.is_some());
+        if let Some(cost) = expected {
+            assert_eq!(grid.path_cost(&dstar.get_path()), cost);
+        }
+        assert!(repair_steps < initial_steps);
+
+        // Opening it up again restores the original route cost
+        grid.set(x, y, Cell::Empty);
+        let changes = grid.take_changes();
+        dstar.notify_changes(&grid, &changes);
+        run(&mut dstar, &grid);
+        assert_eq!(
+            Some(grid.path_cost(&dstar.get_path())),
+            dijkstra_cost(&grid, start, end)
+        );
+    }
+
+    #[test]
+    fn test_repairs_across_wrapped_borders_and_portals() {
+        // Only the way over the left and right edges and a portal cross the wall
+        let mut grid = Grid::new(10, 5);
+        grid.wrap = Wrap::Horizontal;
+        grid.neighborhood = Neighborhood::EightNoCornerCutting;
+        for y in 0..5 {
+            grid.set(4, y, Cell::Wall);
+        }
+        grid.add_portal((2, 0), (6, 4), 5).unwrap();
+        let (start, end) = ((2, 2), (6, 2));
+
+        let mut dstar = DStarLite::new(&grid, start, end, Heuristic::Manhattan);
+        run(&mut dstar, &grid);
+        assert_eq!(
+            Some(grid.path_cost(&dstar.get_path())),
+            dijkstra_cost(&grid, start, end)
+        );
+
+        // Block the far end of the portal, then the border crossing below the start
+        for (x, y) in [(6, 4), (0, 2)] {
+            grid.set(x, y, Cell::Wall);
+            let changes = grid.take_changes();
+            dstar.notify_changes(&grid, &changes);
+            run(&mut dstar, &grid);
+            assert_eq!(
+                Some(grid.path_cost(&dstar.get_path())),
+                dijkstra_cost(&grid, start, end),
+                "after blocking ({x}, {y})"
+            );
+        }
+    }
+
+    #[test]
+    fn test_starts_over_when_portals_change() {
+        // Two ways around the wall: the short one along the top, and the
+        // bottom one, which the portal makes cheaper
+        let mut grid = Grid::new(9, 5);
+        for y in 1..4 {
+            grid.set(4, y, Cell::Wall);
+        }
+        let (start, end) = ((1, 1), (7, 1));
+        let mut dstar = DStarLite::new(&grid, start, end, Heuristic::Manhattan);
+        run(&mut dstar, &grid);
+        assert_eq!(dstar.stats().path_cost, Some(80));
+
+        grid.add_portal((1, 4), (7, 4), 5).unwrap();
+        let changes = grid.take_changes();
+        assert!(!dstar.notify_changes(&grid, &changes));
+        let mut dstar = DStarLite::new(&grid, start, end, Heuristic::Manhattan);
+        run(&mut dstar, &grid);
+        assert_eq!(dstar.stats().path_cost, Some(65));
+        assert_eq!(dstar.stats().path_cost, dijkstra_cost(&grid, start, end));
+
+        // Walls elsewhere are still repaired with the portal in place
+        grid.set(4, 0, Cell::Wall);
+        let changes = grid.take_changes();
+        assert!(dstar.notify_changes(&grid, &changes));
+        run(&mut dstar, &grid);
+        assert_eq!(dstar.stats().path_cost, dijkstra_cost(&grid, start, end));
+
+        grid.remove_portal((7, 4));
+        let changes = grid.take_changes();
+        assert!(!dstar.notify_changes(&grid, &changes));
+    }
+
+    #[test]
+    fn test_starts_over_when_endpoints_change() {
+        let mut grid = Grid::new(10, 3);
+        grid.set(0, 0, Cell::Start);
+        grid.set(9, 0, Cell::End);
+        grid.take_changes();
+        let mut dstar = DStarLite::new(&grid, (0, 0), (9, 0), Heuristic::Manhattan);
+        run(&mut dstar, &grid);
+
+        grid.set(2, 0, Cell::End);
+        let changes = grid.take_changes();
+        assert!(!dstar.notify_changes(&grid, &changes));
+        grid.set(9, 0, Cell::Empty);
+        let changes = grid.take_changes();
+        assert!(!dstar.notify_changes(&grid, &changes));
+        grid.set(5, 1, Cell::Wall);
+        let changes = grid.take_changes();
+        assert!(dstar.notify_changes(&grid, &changes));
+    }
+}
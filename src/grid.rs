@@ -1,3 +1,10 @@
+use crate::astar::Heuristic;
+use crate::hex::{self, HexOrientation};
+use crate::node_map::{NodeMap, NodeSet};
+use crate::pathfinding::PathError;
+use crate::search_space::SearchSpace;
+use std::collections::HashSet;
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Cell {
     Empty,
@@ -6,52 +13,769 @@ pub enum Cell {
     End,
 }
 
+/// Cost of a single horizontal or vertical step, diagonal and knight moves are
+/// scaled from it (√2 ≈ 14/10, √5 ≈ 22/10) so all costs stay integers
+pub const STRAIGHT_COST: u32 = 10;
+pub const DIAGONAL_COST: u32 = 14;
+pub const KNIGHT_COST: u32 = 22;
+
+/// Which cells count as adjacent when moving on the grid
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Neighborhood {
+    /// Up, down, left and right
+    #[default]
+    Four,
+    /// Four plus diagonals
+    Eight,
+    /// Diagonals only when both orthogonally adjacent cells are open
+    EightNoCornerCutting,
+    /// Chess knight jumps, two cells in one direction and one in the other
+    Knight,
+    /// The six sides of hexes, with cells in offset coordinates (see
+    /// `HexOrientation`). Every move costs `STRAIGHT_COST`
+    Hex(HexOrientation),
+}
+
+impl Neighborhood {
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::Four,
+            Self::Eight,
+            Self::EightNoCornerCutting,
+            Self::Knight,
+            Self::Hex(HexOrientation::Pointy),
+            Self::Hex(HexOrientation::Flat),
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Four => "4-way",
+            Self::Eight => "8-way",
+            Self::EightNoCornerCutting => "8-way, no corner cutting",
+            Self::Knight => "Knight",
+            Self::Hex(HexOrientation::Pointy) => "Hex, pointy-top",
+            Self::Hex(HexOrientation::Flat) => "Hex, flat-top",
+        }
+    }
+
+    /// Orientation of the hexes if this is a hex neighborhood
+    pub fn hex_orientation(&self) -> Option<HexOrientation> {
+        match self {
+            Self::Hex(orientation) => Some(*orientation),
+            _ => None,
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let all = Self::all();
+        let current_idx = all.iter().position(|&n| n == *self).unwrap_or(0);
+        all[(current_idx + 1) % all.len()]
+    }
+
+    /// Offsets from (x, y) to its neighbors, only hex neighbors depend on the position
+    fn offsets(&self, x: usize, y: usize) -> &'static [(isize, isize)] {
+        match self {
+            Self::Four => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Self::Eight | Self::EightNoCornerCutting => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+            Self::Knight => &[
+                (-1, -2),
+                (1, -2),
+                (-2, -1),
+                (2, -1),
+                (-2, 1),
+                (2, 1),
+                (-1, 2),
+                (1, 2),
+            ],
+            Self::Hex(orientation) => hex::neighbor_offsets(*orientation, (x, y)),
+        }
+    }
+}
+
+/// Which borders lead around to the opposite side of the grid. Hex grids
+/// keep their offset rows (pointy) or columns (flat) lined up only when the
+/// wrapped dimension is even, see `Grid::effective_wrap`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Wrap {
+    #[default]
+    Off,
+    /// Left and right edges meet
+    Horizontal,
+    /// Top and bottom edges meet
+    Vertical,
+    /// Both, the grid becomes a torus
+    Torus,
+}
+
+impl Wrap {
+    pub fn all() -> &'static [Self] {
+        &[Self::Off, Self::Horizontal, Self::Vertical, Self::Torus]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Horizontal => "Horizontal",
+            Self::Vertical => "Vertical",
+            Self::Torus => "Torus",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let all = Self::all();
+        let current_idx = all.iter().position(|&w| w == *self).unwrap_or(0);
+        all[(current_idx + 1) % all.len()]
+    }
+
+    pub fn horizontal(&self) -> bool {
+        matches!(self, Self::Horizontal | Self::Torus)
+    }
+
+    pub fn vertical(&self) -> bool {
+        matches!(self, Self::Vertical | Self::Torus)
+    }
+}
+
+/// Teleporter linking two cells both ways. Stepping from one end onto the
+/// other costs `cost`, regardless of terrain
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Portal {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+    pub cost: u32,
+}
+
+impl Portal {
+    /// The end linked to `pos`, None if `pos` is not an end of this portal
+    pub fn other_end(&self, pos: (usize, usize)) -> Option<(usize, usize)> {
+        if pos == self.a {
+            Some(self.b)
+        } else if pos == self.b {
+            Some(self.a)
+        } else {
+            None
+        }
+    }
+}
+
+/// Arrow of a one-way cell, see `Grid::set_one_way`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Direction {
+    pub fn all() -> &'static [Self] {
+        &[Self::Up, Self::Right, Self::Down, Self::Left]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Up => "Up",
+            Self::Right => "Right",
+            Self::Down => "Down",
+            Self::Left => "Left",
+        }
+    }
+
+    /// Unit vector with y growing downwards like the rows of the grid
+    pub fn vector(&self) -> (f32, f32) {
+        match self {
+            Self::Up => (0.0, -1.0),
+            Self::Right => (1.0, 0.0),
+            Self::Down => (0.0, 1.0),
+            Self::Left => (-1.0, 0.0),
+        }
+    }
+}
+
+/// Cost of a single move by (dx, dy) on open grass
+pub fn step_cost(dx: isize, dy: isize) -> u32 {
+    match (dx.abs(), dy.abs()) {
+        (0, 1) | (1, 0) => STRAIGHT_COST,
+        (1, 1) => DIAGONAL_COST,
+        _ => KNIGHT_COST,
+    }
+}
+
+/// Ground type of a cell, determines how expensive it is to move onto it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Terrain {
+    #[default]
+    Grass,
+    Sand,
+    Mud,
+    Water,
+}
+
+impl Terrain {
+    pub fn all() -> &'static [Self] {
+        &[Self::Grass, Self::Sand, Self::Mud, Self::Water]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Grass => "Grass",
+            Self::Sand => "Sand",
+            Self::Mud => "Mud",
+            Self::Water => "Water",
+        }
+    }
+
+    pub fn default_cost(&self) -> u32 {
+        match self {
+            Self::Grass => 1,
+            Self::Sand => 2,
+            Self::Mud => 4,
+            Self::Water => 8,
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Self::Grass => 0,
+            Self::Sand => 1,
+            Self::Mud => 2,
+            Self::Water => 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Grid {
     pub width: usize,
     pub height: usize,
-    pub cells: Vec<Vec<Cell>>,
+    /// Row-major, see `index`
+    pub cells: Vec<Cell>,
+    pub terrain: Vec<Terrain>,
+    /// Row-major like `cells`, see `set_one_way`
+    one_way: Vec<Option<Direction>>,
+    pub neighborhood: Neighborhood,
+    pub wrap: Wrap,
+    terrain_costs: [u32; 4],
+    /// At most one portal per cell, see `add_portal`
+    portals: Vec<Portal>,
+    /// Row-major like `cells`, index into `portals` of the portal ending there
+    portal_slots: Vec<Option<usize>>,
+    changes: HashSet<(usize, usize)>,
 }
 
+/// The start of a search and the goals it may end at
+pub type StartAndGoals = ((usize, usize), Vec<(usize, usize)>);
+
 impl Grid {
     pub fn new(width: usize, height: usize) -> Self {
         Self {
             width,
             height,
-            cells: vec![vec![Cell::Empty; width]; height],
+            cells: vec![Cell::Empty; width * height],
+            terrain: vec![Terrain::default(); width * height],
+            one_way: vec![None; width * height],
+            neighborhood: Neighborhood::default(),
+            wrap: Wrap::default(),
+            terrain_costs: [
+                Terrain::Grass.default_cost(),
+                Terrain::Sand.default_cost(),
+                Terrain::Mud.default_cost(),
+                Terrain::Water.default_cost(),
+            ],
+            portals: Vec::new(),
+            portal_slots: vec![None; width * height],
+            changes: HashSet::new(),
         }
     }
 
+    /// Position of (x, y) in `cells` and `terrain`, None when out of bounds
+    pub fn index(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y * self.width + x)
+    }
+
     pub fn get(&self, x: usize, y: usize) -> Option<Cell> {
-        self.cells.get(y).and_then(|row| row.get(x)).copied()
+        self.index(x, y).map(|idx| self.cells[idx])
     }
 
     pub fn set(&mut self, x: usize, y: usize, cell: Cell) {
-        if x < self.width && y < self.height {
-            self.cells[y][x] = cell;
+        if let Some(idx) = self.index(x, y) {
+            if self.cells[idx] != cell {
+                self.cells[idx] = cell;
+                self.changes.insert((x, y));
+            }
         }
     }
 
-    pub fn neighbors(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
-        let mut neighbors = vec![];
+    pub fn get_terrain(&self, x: usize, y: usize) -> Option<Terrain> {
+        self.index(x, y).map(|idx| self.terrain[idx])
+    }
 
-        for dy in -1isize..=1 {
-            for dx in -1isize..=1 {
-                if dy == 0 && dx == 0 {
-                    continue;
-                }
+    pub fn set_terrain(&mut self, x: usize, y: usize, terrain: Terrain) {
+        if let Some(idx) = self.index(x, y) {
+            if self.terrain[idx] != terrain {
+                self.terrain[idx] = terrain;
+                self.changes.insert((x, y));
+            }
+        }
+    }
 
-                let yy = y as isize + dy;
-                let xx = x as isize + dx;
+    pub fn get_one_way(&self, x: usize, y: usize) -> Option<Direction> {
+        self.index(x, y).and_then(|idx| self.one_way[idx])
+    }
 
-                if yy >= 0 && yy < self.height as isize && xx >= 0 && xx < self.width as isize {
-                    // Only add up/down/left/right neighbors, no diagonals
-                    if (dy == 0 && (dx == -1 || dx == 1)) || ((dy == -1 || dy == 1) && dx == 0) {
-                        neighbors.push((xx as usize, yy as usize));
-                    }
-                }
+    /// Makes (x, y) a one-way cell, or a normal one again with None. One-way
+    /// cells can only be left by moves heading the way of their arrow and
+    /// can't be entered by moves heading against it, like conveyor belts or
+    /// one-way doors. Moves through portals are not restricted
+    pub fn set_one_way(&mut self, x: usize, y: usize, direction: Option<Direction>) {
+        if let Some(idx) = self.index(x, y) {
+            if self.one_way[idx] != direction {
+                self.one_way[idx] = direction;
+                self.changes.insert((x, y));
+            }
+        }
+    }
+
+    pub fn has_one_way_cells(&self) -> bool {
+        self.one_way.iter().any(Option::is_some)
+    }
+
+    /// Whether one-way cells allow the step from `from` onto the adjacent
+    /// cell `to`, see `set_one_way`
+    pub fn allows_move(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        let exit = self.get_one_way(from.0, from.1);
+        let entry = self.get_one_way(to.0, to.1);
+        if exit.is_none() && entry.is_none() {
+            return true;
+        }
+
+        let (mx, my) = self.move_vector(from, to);
+        let along = |direction: Direction| {
+            let (ux, uy) = direction.vector();
+            mx * ux + my * uy
+        };
+        exit.is_none_or(|direction| along(direction) > 0.0)
+            && entry.is_none_or(|direction| along(direction) >= 0.0)
+    }
+
+    /// Direction of the step from `from` to `to` on screen, hexes being
+    /// laid out as in `hex::center`
+    fn move_vector(&self, from: (usize, usize), to: (usize, usize)) -> (f32, f32) {
+        let (dx, dy) = self.delta(from, to);
+        let Some(orientation) = self.neighborhood.hex_orientation() else {
+            return (dx as f32, dy as f32);
+        };
+
+        let from = (from.0 as isize, from.1 as isize);
+        let (q0, r0) = hex::offset_to_axial(orientation, from);
+        let (q1, r1) = hex::offset_to_axial(orientation, (from.0 + dx, from.1 + dy));
+        let (dq, dr) = ((q1 - q0) as f32, (r1 - r0) as f32);
+        let sqrt_3 = 3f32.sqrt();
+        match orientation {
+            HexOrientation::Pointy => (sqrt_3 * (dq + dr / 2.0), 1.5 * dr),
+            HexOrientation::Flat => (1.5 * dq, sqrt_3 * (dr + dq / 2.0)),
+        }
+    }
+
+    /// Cells whose content or terrain changed since the last call, for
+    /// algorithms that can repair their search instead of starting over
+    pub fn take_changes(&mut self) -> Vec<(usize, usize)> {
+        self.changes.drain().collect()
+    }
+
+    pub fn terrain_cost(&self, terrain: Terrain) -> u32 {
+        self.terrain_costs[terrain.index()]
+    }
+
+    /// Costs are clamped to at least 1 so that distance heuristics stay admissible
+    pub fn set_terrain_cost(&mut self, terrain: Terrain, cost: u32) {
+        self.terrain_costs[terrain.index()] = cost.max(1);
+    }
+
+    /// Cost of moving onto the cell at (x, y)
+    pub fn cost(&self, x: usize, y: usize) -> u32 {
+        self.terrain_cost(self.get_terrain(x, y).unwrap_or_default())
+    }
+
+    /// Whether every passable cell costs the same to move onto
+    pub fn has_uniform_costs(&self) -> bool {
+        let mut costs = self
+            .cells
+            .iter()
+            .zip(&self.terrain)
+            .filter(|&(&cell, _)| cell != Cell::Wall)
+            .map(|(_, &terrain)| self.terrain_cost(terrain));
+        match costs.next() {
+            Some(first) => costs.all(|cost| cost == first),
+            None => true,
+        }
+    }
+
+    pub fn portals(&self) -> &[Portal] {
+        &self.portals
+    }
+
+    pub fn portal_at(&self, pos: (usize, usize)) -> Option<&Portal> {
+        let slot = self.portal_slots[self.index(pos.0, pos.1)?]?;
+        Some(&self.portals[slot])
+    }
+
+    /// Points both ends of `portal` to its index in `portals`, or to nothing
+    fn set_portal_slot(&mut self, portal: Portal, slot: Option<usize>) {
+        for (x, y) in [portal.a, portal.b] {
+            if let Some(idx) = self.index(x, y) {
+                self.portal_slots[idx] = slot;
+            }
+        }
+    }
+
+    /// Links `a` and `b` with a portal, replacing any portal that already
+    /// ends at either of them. Linking a cell to itself only removes its portal
+    pub fn add_portal(
+        &mut self,
+        a: (usize, usize),
+        b: (usize, usize),
+        cost: u32,
+    ) -> Result<(), PathError> {
+        for (x, y) in [a, b] {
+            if self.index(x, y).is_none() {
+                return Err(PathError::OutOfBounds((x, y)));
+            }
+        }
+        self.remove_portal(a);
+        self.remove_portal(b);
+        if a != b {
+            let portal = Portal { a, b, cost };
+            self.set_portal_slot(portal, Some(self.portals.len()));
+            self.portals.push(portal);
+            self.changes.extend([a, b]);
+        }
+        Ok(())
+    }
+
+    /// Removes the portal ending at `pos` and returns it
+    pub fn remove_portal(&mut self, pos: (usize, usize)) -> Option<Portal> {
+        let slot = self.portal_slots[self.index(pos.0, pos.1)?]?;
+        let portal = self.portals.swap_remove(slot);
+        self.set_portal_slot(portal, None);
+        self.changes.extend([portal.a, portal.b]);
+        // The last portal moved into the freed slot
+        if let Some(&moved) = self.portals.get(slot) {
+            self.set_portal_slot(moved, Some(slot));
+        }
+        Some(portal)
+    }
+
+    pub fn clear_portals(&mut self) {
+        while let Some(portal) = self.portals.last().copied() {
+            self.remove_portal(portal.a);
+        }
+    }
+
+    /// The borders of `wrap` that the grid actually wraps around. Hex grids
+    /// don't wrap an odd number of offset rows (pointy) or columns (flat),
+    /// as the last and first ones wouldn't line up
+    pub fn effective_wrap(&self) -> Wrap {
+        let (mut horizontal, mut vertical) = (self.wrap.horizontal(), self.wrap.vertical());
+        match self.neighborhood {
+            Neighborhood::Hex(HexOrientation::Pointy) => vertical &= self.height.is_multiple_of(2),
+            Neighborhood::Hex(HexOrientation::Flat) => horizontal &= self.width.is_multiple_of(2),
+            _ => {}
+        }
+        match (horizontal, vertical) {
+            (false, false) => Wrap::Off,
+            (true, false) => Wrap::Horizontal,
+            (false, true) => Wrap::Vertical,
+            (true, true) => Wrap::Torus,
+        }
+    }
+
+    /// Position of (x, y) once wrapped around the borders that
+    /// `effective_wrap` joins, None when it lies outside the grid
+    pub fn wrap_position(&self, x: isize, y: isize) -> Option<(usize, usize)> {
+        let wrap = |v: isize, size: usize, wraps: bool| match wraps {
+            true if size > 0 => Some(v.rem_euclid(size as isize) as usize),
+            _ => usize::try_from(v).ok().filter(|&v| v < size),
+        };
+        let wraps = self.effective_wrap();
+        Some((
+            wrap(x, self.width, wraps.horizontal())?,
+            wrap(y, self.height, wraps.vertical())?,
+        ))
+    }
+
+    /// Offset from one cell to another, the short way around wrapped borders
+    pub fn delta(&self, from: (usize, usize), to: (usize, usize)) -> (isize, isize) {
+        let shortest = |d: isize, size: usize, wraps: bool| {
+            if wraps && d.unsigned_abs() * 2 > size {
+                d - d.signum() * size as isize
+            } else {
+                d
+            }
+        };
+        let wraps = self.effective_wrap();
+        (
+            shortest(
+                to.0 as isize - from.0 as isize,
+                self.width,
+                wraps.horizontal(),
+            ),
+            shortest(
+                to.1 as isize - from.1 as isize,
+                self.height,
+                wraps.vertical(),
+            ),
+        )
+    }
+
+    /// Cost of moving from one cell onto an adjacent one, combining the
+    /// length of the move with the terrain cost of the destination. Moves
+    /// through a portal cost the portal's cost, or the cheaper of the two if
+    /// its ends are also adjacent
+    pub fn move_cost(&self, from: (usize, usize), to: (usize, usize)) -> u32 {
+        let (dx, dy) = self.delta(from, to);
+        let step = match self.neighborhood {
+            Neighborhood::Hex(_) => STRAIGHT_COST,
+            _ => step_cost(dx, dy),
+        } * self.cost(to.0, to.1);
+
+        match self
+            .portal_at(from)
+            .filter(|p| p.other_end(from) == Some(to))
+        {
+            Some(portal)
+                if self
+                    .neighborhood
+                    .offsets(from.0, from.1)
+                    .contains(&(dx, dy)) =>
+            {
+                portal.cost.min(step)
             }
+            Some(portal) => portal.cost,
+            None => step,
         }
-        neighbors
+    }
+
+    /// Total `move_cost` along a path of adjacent cells
+    pub fn path_cost(&self, path: &[(usize, usize)]) -> u32 {
+        path.windows(2)
+            .map(|pair| self.move_cost(pair[0], pair[1]))
+            .sum()
+    }
+
+    /// Position of the first cell of the given kind in row-major order
+    pub fn find(&self, cell: Cell) -> Option<(usize, usize)> {
+        self.cells
+            .iter()
+            .position(|&c| c == cell)
+            .map(|idx| (idx % self.width, idx / self.width))
+    }
+
+    /// Positions of all cells of the given kind in row-major order
+    pub fn find_all(&self, cell: Cell) -> Vec<(usize, usize)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|&(_, &c)| c == cell)
+            .map(|(idx, _)| (idx % self.width, idx / self.width))
+            .collect()
+    }
+
+    /// Positions of the start and end cells. There must be exactly one start,
+    /// the first end in row-major order is used
+    pub fn endpoints(&self) -> Result<[(usize, usize); 2], PathError> {
+        let (start, goals) = self.start_and_goals()?;
+        Ok([start, goals[0]])
+    }
+
+    /// Position of the start cell, of which there must be exactly one
+    pub fn start(&self) -> Result<(usize, usize), PathError> {
+        let start = self.find(Cell::Start).ok_or(PathError::NoStart)?;
+        if self.cells.iter().filter(|&&c| c == Cell::Start).count() > 1 {
+            return Err(PathError::MultipleStarts);
+        }
+        Ok(start)
+    }
+
+    /// Position of the single start cell and of every end cell, in
+    /// row-major order
+    pub fn start_and_goals(&self) -> Result<StartAndGoals, PathError> {
+        let start = self.start()?;
+        let goals = self.find_all(Cell::End);
+        if goals.is_empty() {
+            return Err(PathError::NoEnd);
+        }
+        Ok((start, goals))
+    }
+
+    /// Checks that a search can run between the given cells
+    pub fn validate_endpoints(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> Result<(), PathError> {
+        for (x, y) in [start, end] {
+            if self.index(x, y).is_none() {
+                return Err(PathError::OutOfBounds((x, y)));
+            }
+        }
+        if !self.is_passable(start.0, start.1) {
+            return Err(PathError::StartIsWall);
+        }
+        if !self.is_passable(end.0, end.1) {
+            return Err(PathError::EndIsWall);
+        }
+        Ok(())
+    }
+
+    /// Anything but a wall, out of bounds cells are not passable
+    pub fn is_passable(&self, x: usize, y: usize) -> bool {
+        self.get(x, y).is_some_and(|cell| cell != Cell::Wall)
+    }
+
+    pub fn neighbors(&self, x: usize, y: usize) -> Neighbors<'_> {
+        self.neighbors_in(x, y, self.neighborhood)
+    }
+
+    pub fn neighbors_in(&self, x: usize, y: usize, neighborhood: Neighborhood) -> Neighbors<'_> {
+        Neighbors {
+            grid: self,
+            x,
+            y,
+            neighborhood,
+            offsets: neighborhood.offsets(x, y).iter(),
+            portal: self.portal_at((x, y)).and_then(|p| p.other_end((x, y))),
+            passable_only: false,
+            reverse: false,
+        }
+    }
+
+    /// Cells that can move onto (x, y), the reverse of `neighbors`. The two
+    /// only differ around one-way cells
+    pub fn predecessors(&self, x: usize, y: usize) -> Neighbors<'_> {
+        Neighbors {
+            reverse: true,
+            ..self.neighbors(x, y)
+        }
+    }
+}
+
+/// Moves go to the passable `neighbors` of a cell
+impl SearchSpace for Grid {
+    type Node = (usize, usize);
+    type Map<T: Copy + Default> = NodeMap<T>;
+    type Set = NodeSet;
+
+    fn node_map<T: Copy + Default>(&self) -> NodeMap<T> {
+        NodeMap::for_grid(self)
+    }
+
+    fn node_set(&self) -> NodeSet {
+        NodeSet::for_grid(self)
+    }
+
+    fn successors(
+        &self,
+        (x, y): (usize, usize),
+    ) -> impl Iterator<Item = ((usize, usize), u32)> + '_ {
+        self.neighbors(x, y)
+            .passable()
+            .map(move |next| (next, self.move_cost((x, y), next)))
+    }
+
+    /// The tightest admissible heuristic for the neighborhood, wrap and portals
+    fn heuristic(&self, from: (usize, usize), to: (usize, usize)) -> u32 {
+        Heuristic::for_neighborhood(self.neighborhood).estimate_on(self, from, to)
+    }
+
+    fn path_cost(&self, path: &[(usize, usize)]) -> u32 {
+        Grid::path_cost(self, path)
+    }
+}
+
+/// In-bounds cells adjacent to (x, y) across wrapped borders that one-way
+/// cells allow moving to, followed by the other end of a portal at (x, y),
+/// see `Grid::neighbors`
+pub struct Neighbors<'a> {
+    grid: &'a Grid,
+    x: usize,
+    y: usize,
+    neighborhood: Neighborhood,
+    offsets: std::slice::Iter<'static, (isize, isize)>,
+    portal: Option<(usize, usize)>,
+    passable_only: bool,
+    /// Checks one-way cells for moves onto (x, y) instead of away from it
+    reverse: bool,
+}
+
+impl Neighbors<'_> {
+    /// Skips walls
+    pub fn passable(mut self) -> Self {
+        self.passable_only = true;
+        self
+    }
+}
+
+impl Iterator for Neighbors<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for &(dx, dy) in self.offsets.by_ref() {
+            let Some((xx, yy)) = self
+                .grid
+                .wrap_position(self.x as isize + dx, self.y as isize + dy)
+            else {
+                continue;
+            };
+            // Grids narrower than a move wrap back onto the cell itself
+            if (xx, yy) == (self.x, self.y) {
+                continue;
+            }
+
+            if self.neighborhood == Neighborhood::EightNoCornerCutting
+                && dx != 0
+                && dy != 0
+                && (self.grid.get(xx, self.y) == Some(Cell::Wall)
+                    || self.grid.get(self.x, yy) == Some(Cell::Wall))
+            {
+                continue;
+            }
+
+            if self.passable_only && !self.grid.is_passable(xx, yy) {
+                continue;
+            }
+
+            let allowed = match self.reverse {
+                false => self.grid.allows_move((self.x, self.y), (xx, yy)),
+                true => self.grid.allows_move((xx, yy), (self.x, self.y)),
+            };
+            if !allowed {
+                continue;
+            }
+
+            return Some((xx, yy));
+        }
+
+        let portal = self.portal.take()?;
+        if self.passable_only && !self.grid.is_passable(portal.0, portal.1) {
+            return None;
+        }
+        Some(portal)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let portal = usize::from(self.portal.is_some());
+        (0, Some(self.offsets.len() + portal))
     }
 }
 
@@ -64,8 +788,9 @@ mod tests {
         let grid = Grid::new(3, 2);
         assert_eq!(grid.width, 3);
         assert_eq!(grid.height, 2);
-        assert_eq!(grid.cells.len(), 2);
-        assert_eq!(grid.cells[0].len(), 3);
+        assert_eq!(grid.cells.len(), 6);
+        assert_eq!(grid.index(2, 1), Some(5));
+        assert_eq!(grid.index(3, 0), None);
         assert_eq!(grid.get(0, 0), Some(Cell::Empty));
         assert_eq!(grid.get(2, 1), Some(Cell::Empty));
         assert_eq!(grid.get(3, 0), None);
@@ -84,20 +809,283 @@ mod tests {
         assert_eq!(grid.get(2, 0), None);
     }
 
+    #[test]
+    fn test_take_changes() {
+        let mut grid = Grid::new(3, 3);
+        grid.set(1, 1, Cell::Wall);
+        grid.set(1, 1, Cell::Wall);
+        grid.set(0, 0, Cell::Empty); // unchanged
+        grid.set_terrain(2, 2, Terrain::Sand);
+
+        let mut changes = grid.take_changes();
+        changes.sort();
+        assert_eq!(changes, vec![(1, 1), (2, 2)]);
+        assert!(grid.take_changes().is_empty());
+    }
+
+    #[test]
+    fn test_terrain_costs() {
+        let mut grid = Grid::new(2, 2);
+        assert_eq!(grid.get_terrain(0, 0), Some(Terrain::Grass));
+        assert_eq!(grid.cost(0, 0), 1);
+
+        grid.set_terrain(1, 0, Terrain::Water);
+        assert_eq!(grid.get_terrain(1, 0), Some(Terrain::Water));
+        assert_eq!(grid.cost(1, 0), Terrain::Water.default_cost());
+
+        grid.set_terrain_cost(Terrain::Water, 3);
+        assert_eq!(grid.cost(1, 0), 3);
+        // Zero cost would break heuristics, so it is clamped
+        grid.set_terrain_cost(Terrain::Water, 0);
+        assert_eq!(grid.cost(1, 0), 1);
+
+        let mut uniform = Grid::new(3, 1);
+        assert!(uniform.has_uniform_costs());
+        uniform.set_terrain(0, 0, Terrain::Sand);
+        assert!(!uniform.has_uniform_costs());
+        uniform.set(0, 0, Cell::Wall);
+        assert!(uniform.has_uniform_costs());
+    }
+
     #[test]
     fn test_neighbors_center() {
         let grid = Grid::new(3, 3);
-        let neighbors = grid.neighbors(1, 1);
-        let expected = vec![(0, 1), (2, 1), (1, 0), (1, 2)];
+        let neighbors: Vec<_> = grid.neighbors(1, 1).collect();
+        let expected = [(0, 1), (2, 1), (1, 0), (1, 2)];
         neighbors.iter().for_each(|n| assert!(expected.contains(n)));
         assert_eq!(neighbors.len(), 4);
     }
 
+    #[test]
+    fn test_neighbors_eight() {
+        let mut grid = Grid::new(3, 3);
+        grid.neighborhood = Neighborhood::Eight;
+        assert_eq!(grid.neighbors(1, 1).count(), 8);
+        assert_eq!(grid.neighbors(0, 0).count(), 3);
+    }
+
+    #[test]
+    fn test_neighbors_no_corner_cutting() {
+        let mut grid = Grid::new(3, 3);
+        grid.neighborhood = Neighborhood::EightNoCornerCutting;
+        grid.set(1, 0, Cell::Wall);
+        let neighbors: Vec<_> = grid.neighbors(1, 1).collect();
+        // Both upper diagonals squeeze past the wall at (1, 0)
+        assert!(!neighbors.contains(&(0, 0)));
+        assert!(!neighbors.contains(&(2, 0)));
+        assert!(neighbors.contains(&(0, 2)));
+        assert_eq!(neighbors.len(), 6);
+    }
+
+    #[test]
+    fn test_neighbors_knight() {
+        let mut grid = Grid::new(5, 5);
+        grid.neighborhood = Neighborhood::Knight;
+        assert_eq!(grid.neighbors(2, 2).count(), 8);
+        let expected = [(2, 1), (1, 2)];
+        let neighbors: Vec<_> = grid.neighbors(0, 0).collect();
+        neighbors.iter().for_each(|n| assert!(expected.contains(n)));
+        assert_eq!(neighbors.len(), 2);
+    }
+
+    #[test]
+    fn test_neighbors_hex() {
+        let mut grid = Grid::new(5, 5);
+        grid.neighborhood = Neighborhood::Hex(HexOrientation::Pointy);
+        assert_eq!(grid.neighbors(2, 2).count(), 6);
+        let mut odd_row: Vec<_> = grid.neighbors(2, 1).collect();
+        odd_row.sort();
+        assert_eq!(odd_row, [(1, 1), (2, 0), (2, 2), (3, 0), (3, 1), (3, 2)]);
+        assert_eq!(grid.move_cost((2, 1), (3, 2)), STRAIGHT_COST);
+
+        grid.neighborhood = Neighborhood::Hex(HexOrientation::Flat);
+        let mut corner: Vec<_> = grid.neighbors(0, 0).collect();
+        corner.sort();
+        assert_eq!(corner, [(0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn test_neighbors_wrap() {
+        let mut grid = Grid::new(4, 3);
+        grid.wrap = Wrap::Horizontal;
+        let mut corner: Vec<_> = grid.neighbors(0, 0).collect();
+        corner.sort();
+        assert_eq!(corner, [(0, 1), (1, 0), (3, 0)]);
+        assert_eq!(grid.move_cost((0, 0), (3, 0)), STRAIGHT_COST);
+
+        grid.wrap = Wrap::Torus;
+        grid.neighborhood = Neighborhood::Eight;
+        assert_eq!(grid.neighbors(0, 0).count(), 8);
+        assert_eq!(grid.move_cost((0, 0), (3, 2)), DIAGONAL_COST);
+        assert_eq!(grid.delta((3, 2), (0, 0)), (1, 1));
+        assert_eq!(grid.wrap_position(-1, 4), Some((3, 1)));
+    }
+
+    #[test]
+    fn test_hex_wrap_on_odd_dimension() {
+        // 5 offset rows (pointy) or columns (flat) can't line up across the
+        // border, that axis doesn't wrap and adjacency stays symmetric
+        let mut grid = Grid::new(5, 5);
+        grid.wrap = Wrap::Torus;
+        for (orientation, wraps) in [
+            (HexOrientation::Pointy, Wrap::Horizontal),
+            (HexOrientation::Flat, Wrap::Vertical),
+        ] {
+            grid.neighborhood = Neighborhood::Hex(orientation);
+            assert_eq!(grid.effective_wrap(), wraps);
+            for (x, y) in (0..5).flat_map(|y| (0..5).map(move |x| (x, y))) {
+                for (nx, ny) in grid.neighbors(x, y) {
+                    assert!(grid.neighbors(nx, ny).any(|n| n == (x, y)));
+                }
+            }
+        }
+
+        let mut grid = Grid::new(6, 6);
+        grid.wrap = Wrap::Torus;
+        grid.neighborhood = Neighborhood::Hex(HexOrientation::Flat);
+        assert_eq!(grid.effective_wrap(), Wrap::Torus);
+        assert_eq!(grid.neighbors(0, 0).count(), 6);
+    }
+
+    #[test]
+    fn test_portals() {
+        let mut grid = Grid::new(5, 5);
+        assert_eq!(
+            grid.add_portal((0, 0), (5, 0), 3),
+            Err(PathError::OutOfBounds((5, 0)))
+        );
+        grid.add_portal((0, 0), (4, 4), 3).unwrap();
+        assert!(grid.neighbors(0, 0).any(|n| n == (4, 4)));
+        assert!(grid.neighbors(4, 4).any(|n| n == (0, 0)));
+        assert_eq!(grid.move_cost((4, 4), (0, 0)), 3);
+
+        // Ends that are also neighbors take the cheaper move
+        grid.add_portal((1, 1), (1, 2), 30).unwrap();
+        assert_eq!(grid.move_cost((1, 1), (1, 2)), STRAIGHT_COST);
+
+        grid.set(4, 4, Cell::Wall);
+        assert!(!grid.neighbors(0, 0).passable().any(|n| n == (4, 4)));
+
+        // A new portal replaces the old ones at its ends
+        grid.add_portal((0, 0), (1, 1), 5).unwrap();
+        assert_eq!(grid.portals().len(), 1);
+        assert_eq!(
+            grid.portal_at((1, 1)).and_then(|p| p.other_end((1, 1))),
+            Some((0, 0))
+        );
+        assert_eq!(grid.remove_portal((0, 0)).map(|p| p.cost), Some(5));
+        assert!(grid.portals().is_empty());
+        assert_eq!(grid.portal_at((1, 1)), None);
+
+        // Adding and removing portals changes both ends
+        grid.take_changes();
+        grid.add_portal((0, 1), (3, 3), 4).unwrap();
+        grid.add_portal((2, 0), (2, 4), 4).unwrap();
+        let mut changes = grid.take_changes();
+        changes.sort();
+        assert_eq!(changes, vec![(0, 1), (2, 0), (2, 4), (3, 3)]);
+        grid.remove_portal((3, 3));
+        let mut changes = grid.take_changes();
+        changes.sort();
+        assert_eq!(changes, vec![(0, 1), (3, 3)]);
+        assert_eq!(grid.portal_at((2, 4)).map(|p| p.a), Some((2, 0)));
+    }
+
+    #[test]
+    fn test_one_way() {
+        let mut grid = Grid::new(3, 3);
+        grid.set_one_way(1, 1, Some(Direction::Right));
+        assert_eq!(grid.get_one_way(1, 1), Some(Direction::Right));
+        assert!(grid.has_one_way_cells());
+        assert_eq!(grid.neighbors(1, 1).collect::<Vec<_>>(), [(2, 1)]);
+        // Entered from anywhere but the cell its arrow points to
+        let mut predecessors: Vec<_> = grid.predecessors(1, 1).collect();
+        predecessors.sort();
+        assert_eq!(predecessors, [(0, 1), (1, 0), (1, 2)]);
+        assert!(!grid.neighbors(2, 1).any(|n| n == (1, 1)));
+
+        grid.neighborhood = Neighborhood::Hex(HexOrientation::Pointy);
+        let mut exits: Vec<_> = grid.neighbors(1, 1).collect();
+        exits.sort();
+        assert_eq!(exits, [(2, 0), (2, 1), (2, 2)]);
+
+        grid.set_one_way(1, 1, None);
+        assert!(!grid.has_one_way_cells());
+        assert_eq!(grid.take_changes(), [(1, 1)]);
+    }
+
+    #[test]
+    fn test_passable_neighbors() {
+        let mut grid = Grid::new(3, 3);
+        grid.set(1, 0, Cell::Wall);
+        grid.set(0, 1, Cell::Start);
+        let neighbors: Vec<_> = grid.neighbors(1, 1).passable().collect();
+        assert_eq!(neighbors, vec![(0, 1), (2, 1), (1, 2)]);
+        assert!(!grid.is_passable(1, 0));
+        assert!(!grid.is_passable(3, 0));
+    }
+
+    #[test]
+    fn test_move_cost() {
+        let mut grid = Grid::new(3, 3);
+        assert_eq!(grid.move_cost((0, 0), (1, 0)), STRAIGHT_COST);
+        assert_eq!(grid.move_cost((0, 0), (1, 1)), DIAGONAL_COST);
+        assert_eq!(grid.move_cost((0, 0), (1, 2)), KNIGHT_COST);
+        grid.set_terrain(1, 1, Terrain::Sand);
+        assert_eq!(grid.move_cost((0, 0), (1, 1)), DIAGONAL_COST * 2);
+        assert_eq!(
+            grid.path_cost(&[(0, 0), (1, 1), (2, 1)]),
+            DIAGONAL_COST * 2 + STRAIGHT_COST
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        let mut grid = Grid::new(3, 3);
+        assert_eq!(grid.find(Cell::Start), None);
+        grid.set(2, 1, Cell::Start);
+        assert_eq!(grid.find(Cell::Start), Some((2, 1)));
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let mut grid = Grid::new(4, 4);
+        assert_eq!(grid.endpoints(), Err(PathError::NoStart));
+        grid.set(1, 1, Cell::Start);
+        assert_eq!(grid.endpoints(), Err(PathError::NoEnd));
+        grid.set(3, 2, Cell::End);
+        assert_eq!(grid.endpoints(), Ok([(1, 1), (3, 2)]));
+        grid.set(2, 0, Cell::End);
+        assert_eq!(grid.endpoints(), Ok([(1, 1), (2, 0)]));
+        assert_eq!(grid.start_and_goals(), Ok(((1, 1), vec![(2, 0), (3, 2)])));
+        grid.set(0, 3, Cell::Start);
+        assert_eq!(grid.endpoints(), Err(PathError::MultipleStarts));
+    }
+
+    #[test]
+    fn test_validate_endpoints() {
+        let mut grid = Grid::new(4, 4);
+        grid.set(2, 2, Cell::Wall);
+        assert_eq!(grid.validate_endpoints((0, 0), (3, 3)), Ok(()));
+        assert_eq!(
+            grid.validate_endpoints((0, 4), (3, 3)),
+            Err(PathError::OutOfBounds((0, 4)))
+        );
+        assert_eq!(
+            grid.validate_endpoints((2, 2), (3, 3)),
+            Err(PathError::StartIsWall)
+        );
+        assert_eq!(
+            grid.validate_endpoints((0, 0), (2, 2)),
+            Err(PathError::EndIsWall)
+        );
+    }
+
     #[test]
     fn test_neighbors_corner() {
         let grid = Grid::new(3, 3);
-        let neighbors = grid.neighbors(0, 0);
-        let expected = vec![(1, 0), (0, 1)];
+        let neighbors: Vec<_> = grid.neighbors(0, 0).collect();
+        let expected = [(1, 0), (0, 1)];
         neighbors.iter().for_each(|n| assert!(expected.contains(n)));
         assert_eq!(neighbors.len(), 2);
     }
@@ -105,8 +1093,8 @@ mod tests {
     #[test]
     fn test_neighbors_edge() {
         let grid = Grid::new(3, 3);
-        let neighbors = grid.neighbors(2, 1);
-        let expected = vec![(1, 1), (2, 0), (2, 2)];
+        let neighbors: Vec<_> = grid.neighbors(2, 1).collect();
+        let expected = [(1, 1), (2, 0), (2, 2)];
         neighbors.iter().for_each(|n| assert!(expected.contains(n)));
         assert_eq!(neighbors.len(), 3);
     }
@@ -0,0 +1,182 @@
+/// Which way the hexes of a hex grid point. Grids store hexes in offset
+/// coordinates: pointy-top hexes form rows with every odd row pushed right by
+/// half a hex ("odd-r"), flat-top hexes form columns with every odd column
+/// pushed down by half a hex ("odd-q")
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HexOrientation {
+    #[default]
+    Pointy,
+    Flat,
+}
+
+const SQRT_3: f32 = 1.732_050_8;
+
+/// Offsets to the six neighbors of a hex, which depend on whether its row
+/// (pointy) or column (flat) is odd
+pub fn neighbor_offsets(
+    orientation: HexOrientation,
+    (x, y): (usize, usize),
+) -> &'static [(isize, isize)] {
+    match (orientation, y % 2, x % 2) {
+        (HexOrientation::Pointy, 0, _) => &[(1, 0), (0, -1), (-1, -1), (-1, 0), (-1, 1), (0, 1)],
+        (HexOrientation::Pointy, _, _) => &[(1, 0), (1, -1), (0, -1), (-1, 0), (0, 1), (1, 1)],
+        (HexOrientation::Flat, _, 0) => &[(1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (0, 1)],
+        (HexOrientation::Flat, _, _) => &[(1, 1), (1, 0), (0, -1), (-1, 0), (-1, 1), (0, 1)],
+    }
+}
+
+/// Axial coordinates (q, r) of the hex at offset coordinates (x, y), which
+/// may lie outside the grid
+pub fn offset_to_axial(orientation: HexOrientation, (x, y): (isize, isize)) -> (isize, isize) {
+    match orientation {
+        HexOrientation::Pointy => (x - (y - (y & 1)) / 2, y),
+        HexOrientation::Flat => (x, y - (x - (x & 1)) / 2),
+    }
+}
+
+pub fn axial_to_offset(orientation: HexOrientation, (q, r): (isize, isize)) -> (isize, isize) {
+    match orientation {
+        HexOrientation::Pointy => (q + (r - (r & 1)) / 2, r),
+        HexOrientation::Flat => (q, r + (q - (q & 1)) / 2),
+    }
+}
+
+/// Moves between two hexes given in axial coordinates
+pub fn axial_distance(a: (isize, isize), b: (isize, isize)) -> u32 {
+    let dq = a.0 - b.0;
+    let dr = a.1 - b.1;
+    ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as u32
+}
+
+/// Moves between two hexes given in offset coordinates
+pub fn distance(orientation: HexOrientation, from: (usize, usize), to: (usize, usize)) -> u32 {
+    let axial = |(x, y): (usize, usize)| offset_to_axial(orientation, (x as isize, y as isize));
+    axial_distance(axial(from), axial(to))
+}
+
+/// Size of a `width` by `height` hex grid in pixels, `size` being the
+/// distance from the center of a hex to its corners
+pub fn pixel_size(
+    orientation: HexOrientation,
+    size: f32,
+    width: usize,
+    height: usize,
+) -> (f32, f32) {
+    let (width, height) = (width as f32, height as f32);
+    match orientation {
+        HexOrientation::Pointy => (SQRT_3 * size * (width + 0.5), size * (1.5 * height + 0.5)),
+        HexOrientation::Flat => (size * (1.5 * width + 0.5), SQRT_3 * size * (height + 0.5)),
+    }
+}
+
+/// Largest hex size for which a `width` by `height` grid fits into the
+/// given pixel area
+pub fn fit_size(orientation: HexOrientation, width: usize, height: usize, area: (f32, f32)) -> f32 {
+    let (unit_width, unit_height) = pixel_size(orientation, 1.0, width, height);
+    (area.0 / unit_width).min(area.1 / unit_height)
+}
+
+/// Center of the hex at (x, y) in pixels, with the grid's bounding box
+/// starting at the origin
+pub fn center(orientation: HexOrientation, size: f32, (x, y): (usize, usize)) -> (f32, f32) {
+    let (x, y) = (x as f32, y as f32);
+    let half_width = SQRT_3 / 2.0 * size;
+    match orientation {
+        HexOrientation::Pointy => {
+            let shift = if y % 2.0 == 1.0 { 0.5 } else { 0.0 };
+            (
+                SQRT_3 * size * (x + shift) + half_width,
+                1.5 * size * y + size,
+            )
+        }
+        HexOrientation::Flat => {
+            let shift = if x % 2.0 == 1.0 { 0.5 } else { 0.0 };
+            (
+                1.5 * size * x + size,
+                SQRT_3 * size * (y + shift) + half_width,
+            )
+        }
+    }
+}
+
+/// Hex in offset coordinates under a pixel, the inverse of `center`. The
+/// result lies outside the grid for pixels outside of it
+pub fn hex_at(orientation: HexOrientation, size: f32, (px, py): (f32, f32)) -> (isize, isize) {
+    let (cx, cy) = center(orientation, size, (0, 0));
+    let (px, py) = (px - cx, py - cy);
+    let (q, r) = match orientation {
+        HexOrientation::Pointy => ((SQRT_3 / 3.0 * px - py / 3.0) / size, 2.0 / 3.0 * py / size),
+        HexOrientation::Flat => (
+            2.0 / 3.0 * px / size,
+            (-px / 3.0 + SQRT_3 / 3.0 * py) / size,
+        ),
+    };
+    axial_to_offset(orientation, round_axial(q, r))
+}
+
+/// Nearest hex to fractional axial coordinates
+fn round_axial(q: f32, r: f32) -> (isize, isize) {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as isize, rr as isize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIENTATIONS: [HexOrientation; 2] = [HexOrientation::Pointy, HexOrientation::Flat];
+
+    #[test]
+    fn test_neighbors_are_one_move_away() {
+        for orientation in ORIENTATIONS {
+            for pos in [(4, 4), (4, 5), (5, 4), (5, 5)] {
+                for &(dx, dy) in neighbor_offsets(orientation, pos) {
+                    let next = (pos.0.wrapping_add_signed(dx), pos.1.wrapping_add_signed(dy));
+                    assert_eq!(
+                        distance(orientation, pos, next),
+                        1,
+                        "{orientation:?} {pos:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_axial_round_trip() {
+        for orientation in ORIENTATIONS {
+            for pos in [(0, 0), (3, 1), (-2, -3), (5, 6)] {
+                let axial = offset_to_axial(orientation, pos);
+                assert_eq!(axial_to_offset(orientation, axial), pos);
+            }
+        }
+        assert_eq!(distance(HexOrientation::Pointy, (0, 0), (3, 0)), 3);
+        assert_eq!(distance(HexOrientation::Pointy, (0, 0), (0, 4)), 4);
+        assert_eq!(distance(HexOrientation::Pointy, (0, 0), (3, 4)), 5);
+    }
+
+    #[test]
+    fn test_pixel_picking() {
+        for orientation in ORIENTATIONS {
+            for pos in [(0, 0), (1, 0), (0, 1), (7, 3), (4, 8)] {
+                let (cx, cy) = center(orientation, 12.0, pos);
+                for (dx, dy) in [(0.0, 0.0), (5.0, 3.0), (-4.0, 5.0), (3.0, -6.0)] {
+                    let picked = hex_at(orientation, 12.0, (cx + dx, cy + dy));
+                    assert_eq!(picked, (pos.0 as isize, pos.1 as isize), "{orientation:?}");
+                }
+            }
+            assert!(hex_at(orientation, 12.0, (-20.0, -20.0)).0 < 0);
+
+            let size = fit_size(orientation, 10, 8, (200.0, 200.0));
+            let (width, height) = pixel_size(orientation, size, 10, 8);
+            assert!(width <= 200.001 && height <= 200.001);
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use crate::astar::Heuristic;
+use crate::grid::{Grid, Wrap, DIAGONAL_COST, STRAIGHT_COST};
+use crate::node_map::{NodeMap, NodeSet};
+use crate::pathfinding::{
+    EventKind, NodeState, PathfindingAlgorithm, SearchEvent, SearchLog, SearchStats,
+};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+#[derive(Eq, PartialEq)]
+struct Node {
+    position: (usize, usize),
+    g_cost: u32,
+    f_cost: u32,
+}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match other.f_cost.cmp(&self.f_cost) {
+            Ordering::Equal => self.g_cost.cmp(&other.g_cost),
+            other => other,
+        }
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn walkable(grid: &Grid, x: isize, y: isize) -> bool {
+    grid.wrap_position(x, y)
+        .is_some_and(|(x, y)| grid.is_passable(x, y))
+}
+
+/// Jump Point Search on a uniform-cost 8-connected grid where diagonal moves
+/// may not cut corners. Paths on any other grid are invalid, which is why
+/// `AlgorithmType::supports` only offers it on `EightNoCornerCutting` grids
+/// with uniform terrain costs.
+///
+/// Instead of pushing every neighbor, JPS scans in straight and diagonal lines
+/// and only queues the "jump points" where the optimal path may turn. The
+/// cells passed over by a scan are shown as `NodeState::Scanned`. Scans run
+/// across wrapped borders and stop on portals, which are taken like any
+/// other move. Pruning relies on moves being reversible, so on grids with
+/// one-way cells every step is a jump point and JPS expands like A*.
+pub struct JumpPointSearch {
+    g_costs: NodeMap<u32>,
+    parents: NodeMap<(usize, usize)>,
+    /// Direction of the scan that reached a jump point, (0, 0) at the start
+    /// and at the far end of a portal
+    arrivals: NodeMap<(isize, isize)>,
+    visited: NodeSet,
+    heuristic: Heuristic,
+    size: (usize, usize),
+    wrap: Wrap,
+    /// Set on grids with one-way cells, see the type's documentation
+    single_steps: bool,
+    log: SearchLog,
+    queue: BinaryHeap<Node>,
+    stats: SearchStats,
+    start: (usize, usize),
+    goals: Vec<(usize, usize)>,
+    /// The goal the path leads to, the first of `goals` until one is reached
+    end: (usize, usize),
+    finished: bool,
+    found_path: bool,
+}
+
+impl JumpPointSearch {
+    pub fn new(grid: &Grid, start: (usize, usize), end: (usize, usize)) -> Self {
+        Self::to_nearest(grid, start, &[end])
+    }
+
+    /// Stops at whichever of `goals` is the cheapest to reach
+    pub fn to_nearest(grid: &Grid, start: (usize, usize), goals: &[(usize, usize)]) -> Self {
+        let mut jps = JumpPointSearch {
+            g_costs: NodeMap::for_grid(grid),
+            parents: NodeMap::for_grid(grid),
+            arrivals: NodeMap::for_grid(grid),
+            visited: NodeSet::for_grid(grid),
+            heuristic: Heuristic::Octile.on_grid(grid),
+            size: (grid.width, grid.height),
+            wrap: grid.effective_wrap(),
+            single_steps: grid.has_one_way_cells(),
+            log: SearchLog::new(grid),
+            queue: BinaryHeap::new(),
+            stats: SearchStats::default(),
+            start,
+            goals: goals.to_vec(),
+            end: goals.first().copied().unwrap_or(start),
+            finished: false,
+            found_path: false,
+        };
+
+        jps.g_costs.insert(start, 0);
+        jps.arrivals.insert(start, (0, 0));
+        jps.queue.push(Node {
+            position: start,
+            g_cost: 0,
+            f_cost: jps.estimate(start),
+        });
+        jps.log
+            .record(jps.stats.steps, EventKind::Push, start, NodeState::InQueue);
+        jps.stats.record_push(1);
+
+        jps
+    }
+
+    /// Estimate to the closest goal
+    fn estimate(&self, pos: (usize, usize)) -> u32 {
+        self.goals
+            .iter()
+            .map(|&goal| self.heuristic.estimate(pos, goal))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Directions worth scanning from `pos`, pruned by the direction we arrived from
+    fn directions(&self, grid: &Grid, pos: (usize, usize)) -> Vec<(isize, isize)> {
+        let (x, y) = (pos.0 as isize, pos.1 as isize);
+        let w = |dx: isize, dy: isize| walkable(grid, x + dx, y + dy);
+        let mut directions = vec![];
+
+        let arrival = self
+            .arrivals
+            .get(pos)
+            .filter(|&arrival| arrival != (0, 0) && !self.single_steps);
+        let Some((dx, dy)) = arrival else {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if (dx, dy) == (0, 0) || !w(dx, dy) {
+                        continue;
+                    }
+                    if dx != 0 && dy != 0 && !(w(dx, 0) && w(0, dy)) {
+                        continue;
+                    }
+                    directions.push((dx, dy));
+                }
+            }
+            return directions;
+        };
+
+        if dx != 0 && dy != 0 {
+            if w(0, dy) {
+                directions.push((0, dy));
+            }
+            if w(dx, 0) {
+                directions.push((dx, 0));
+            }
+            if w(0, dy) && w(dx, 0) {
+                directions.push((dx, dy));
+            }
+        } else if dx != 0 {
+            let (next, below, above) = (w(dx, 0), w(0, 1), w(0, -1));
+            if next {
+                directions.push((dx, 0));
+                if below {
+                    directions.push((dx, 1));
+                }
+                if above {
+                    directions.push((dx, -1));
+                }
+            }
+            if below {
+                directions.push((0, 1));
+            }
+            if above {
+                directions.push((0, -1));
+            }
+        } else {
+            let (next, right, left) = (w(0, dy), w(1, 0), w(-1, 0));
+            if next {
+                directions.push((0, dy));
+                if right {
+                    directions.push((1, dy));
+                }
+                if left {
+                    directions.push((-1, dy));
+                }
+            }
+            if right {
+                directions.push((1, 0));
+            }
+            if left {
+                directions.push((-1, 0));
+            }
+        }
+
+        directions
+    }
+
+    /// Scan from `from` in direction (dx, dy) until a jump point, the goal, a
+    /// portal or an obstacle is hit, returning it along with the number of
+    /// steps taken. Straight scans are started from every diagonal step.
+    fn jump(
+        &mut self,
+        grid: &Grid,
+        from: (usize, usize),
+        dx: isize,
+        dy: isize,
+    ) -> Option<((usize, usize), u32)> {
+        let w = |x: isize, y: isize| walkable(grid, x, y);
+        let mut x = from.0 as isize + dx;
+        let mut y = from.1 as isize + dy;
+        let mut steps = 1;
+
+        loop {
+            if !w(x, y) {
+                return None;
+            }
+
+            let pos = grid.wrap_position(x, y)?;
+            // Came all the way around a wrapped grid
+            if pos == from {
+                return None;
+            }
+            if self.single_steps && !grid.allows_move(from, pos) {
+                return None;
+            }
+            if self.log.state(pos) == NodeState::Unvisited {
+                self.log
+                    .record(self.stats.steps, EventKind::Scan, pos, NodeState::Scanned);
+            }
+
+            if self.goals.contains(&pos) || grid.portal_at(pos).is_some() || self.single_steps {
+                return Some((pos, steps));
+            }
+
+            if dx != 0 && dy != 0 {
+                if self.jump(grid, pos, dx, 0).is_some() || self.jump(grid, pos, 0, dy).is_some() {
+                    return Some((pos, steps));
+                }
+            } else if dx != 0 {
+                if (w(x, y - 1) && !w(x - dx, y - 1)) || (w(x, y + 1) && !w(x - dx, y + 1)) {
+                    return Some((pos, steps));
+                }
+            } else if (w(x - 1, y) && !w(x - 1, y - dy)) || (w(x + 1, y) && !w(x + 1, y - dy)) {
+                return Some((pos, steps));
+            }
+
+            // Diagonal steps need both orthogonal cells open
+            if !(w(x + dx, y) && w(x, y + dy)) {
+                return None;
+            }
+            x += dx;
+            y += dy;
+            steps += 1;
+        }
+    }
+
+    /// One step from `pos` in direction (dx, dy), across wrapped borders
+    fn advance(&self, pos: (usize, usize), (dx, dy): (isize, isize)) -> (usize, usize) {
+        let advance = |v: usize, d: isize, size: usize, wraps: bool| {
+            let v = v as isize + d;
+            match wraps {
+                true => v.rem_euclid(size as isize) as usize,
+                false => v as usize,
+            }
+        };
+        (
+            advance(pos.0, dx, self.size.0, self.wrap.horizontal()),
+            advance(pos.1, dy, self.size.1, self.wrap.vertical()),
+        )
+    }
+
+    fn relax(
+        &mut self,
+        from: (usize, usize),
+        to: (usize, usize),
+        new_g: u32,
+        arrival: (isize, isize),
+    ) {
+        let old_g = self.g_costs.get(to).unwrap_or(u32::MAX);
+        if new_g >= old_g {
+            return;
+        }
+
+        self.g_costs.insert(to, new_g);
+        self.parents.insert(to, from);
+        self.arrivals.insert(to, arrival);
+        self.queue.push(Node {
+            position: to,
+            g_cost: new_g,
+            f_cost: new_g + self.estimate(to),
+        });
+        self.stats.record_push(self.queue.len());
+        let kind = if old_g == u32::MAX {
+            EventKind::Push
+        } else {
+            EventKind::Relax
+        };
+        self.log
+            .record(self.stats.steps, kind, to, NodeState::InQueue);
+    }
+
+    /// Jump points joined by the straight or diagonal lines between them
+    fn expanded_path(&self) -> Vec<(usize, usize)> {
+        let mut jump_points = vec![self.end];
+        let mut current = self.end;
+        while current != self.start {
+            match self.parents.get(current) {
+                Some(parent) => {
+                    jump_points.push(parent);
+                    current = parent;
+                }
+                None => break,
+            }
+        }
+        jump_points.reverse();
+
+        let mut path = vec![jump_points[0]];
+        for pair in jump_points.windows(2) {
+            let arrival = self.arrivals.get(pair[1]).unwrap_or((0, 0));
+            if arrival == (0, 0) {
+                // Through a portal
+                path.push(pair[1]);
+                continue;
+            }
+            let mut pos = pair[0];
+            while pos != pair[1] {
+                pos = self.advance(pos, arrival);
+                path.push(pos);
+            }
+        }
+        path
+    }
+
+    fn mark_path(&mut self) {
+        for pos in self.expanded_path() {
+            self.log
+                .record(self.stats.steps, EventKind::PathMark, pos, NodeState::Path);
+        }
+    }
+}
+
+impl PathfindingAlgorithm for JumpPointSearch {
+    fn step(&mut self, grid: &Grid) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        let current = match self.queue.pop() {
+            Some(node) => node,
+            None => {
+                self.finished = true;
+                return false;
+            }
+        };
+
+        let pos = current.position;
+        self.stats.steps += 1;
+
+        if self.visited.contains(pos) {
+            self.stats.duplicate_pops += 1;
+            return true;
+        }
+
+        self.visited.insert(pos);
+        self.log
+            .record(self.stats.steps, EventKind::Pop, pos, NodeState::Visited);
+        self.stats.nodes_expanded += 1;
+
+        if self.goals.contains(&pos) {
+            self.end = pos;
+            self.finished = true;
+            self.found_path = true;
+            self.stats.path_cost = Some(grid.path_cost(&self.get_path()));
+            self.mark_path();
+            return false;
+        }
+
+        let current_g = self.g_costs.get(pos).unwrap_or(u32::MAX);
+
+        for (dx, dy) in self.directions(grid, pos) {
+            let Some((jump_point, steps)) = self.jump(grid, pos, dx, dy) else {
+                continue;
+            };
+
+            if self.visited.contains(jump_point) {
+                continue;
+            }
+
+            // Terrain costs the same everywhere, so any cell's cost will do
+            let step_cost = if dx != 0 && dy != 0 {
+                DIAGONAL_COST
+            } else {
+                STRAIGHT_COST
+            } * grid.cost(jump_point.0, jump_point.1);
+            self.relax(pos, jump_point, current_g + steps * step_cost, (dx, dy));
+        }
+
+        if let Some(portal) = grid.portal_at(pos).copied() {
+            let far_end = portal.other_end(pos).unwrap_or(pos);
+            if grid.is_passable(far_end.0, far_end.1) && !self.visited.contains(far_end) {
+                self.relax(pos, far_end, current_g + portal.cost, (0, 0));
+            }
+        }
+
+        true
+    }
+
+    fn get_node_state(&self, pos: (usize, usize)) -> NodeState {
+        self.log.state(pos)
+    }
+
+    fn get_path(&self) -> Vec<(usize, usize)> {
+        if !self.found_path {
+            return Vec::new();
+        }
+        self.expanded_path()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn found_path(&self) -> bool {
+        self.found_path
+    }
+
+    fn name(&self) -> &'static str {
+        "JPS"
+    }
+
+    fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    fn events(&self) -> &[SearchEvent] {
+        self.log.events()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cellular_automata::CellularAutomata;
+    use crate::dijkstra::Dijkstra;
+    use crate::grid::{Cell, Neighborhood, Wrap};
+    use crate::map_generator::MapGenerator;
+
+    #[test]
+    fn test_open_grid_diagonal() {
+        let mut grid = Grid::new(6, 4);
+        grid.neighborhood = Neighborhood::EightNoCornerCutting;
+        let mut jps = JumpPointSearch::new(&grid, (0, 0), (5, 3));
+        while jps.step(&grid) {}
+
+        let path = jps.get_path();
+        assert_eq!(path.len(), 6);
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert_eq!(path.last(), Some(&(5, 3)));
+    }
+
+    #[test]
+    fn test_matches_dijkstra_on_caves() {
+        for seed in 1..6 {
+            let mut grid = Grid::new(40, 40);
+            CellularAutomata {
+                seed,
+                ..Default::default()
+            }
+            .generate(&mut grid);
+            grid.neighborhood = Neighborhood::EightNoCornerCutting;
+            let [start, end] = grid.endpoints().unwrap();
+
+            let mut dijkstra = Dijkstra::new(&grid, start, end);
+            while dijkstra.step(&grid) {}
+            let mut jps = JumpPointSearch::new(&grid, start, end);
+            while jps.step(&grid) {}
+
+            let path = jps.get_path();
+            let neighbors_ok = path
+                .windows(2)
+                .all(|pair| grid.neighbors(pair[0].0, pair[0].1).any(|n| n == pair[1]));
+            let cost: u32 = path
+                .windows(2)
+                .map(|pair| grid.move_cost(pair[0], pair[1]))
+                .sum();
+
+            assert!(neighbors_ok, "seed {seed}: invalid move in path");
+            assert_eq!(cost, dijkstra.distances.get(end).unwrap(), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn test_matches_dijkstra_on_wrapped_caves_with_portals() {
+        for seed in 1..6 {
+            let mut grid = Grid::new(40, 30);
+            CellularAutomata {
+                seed,
+                ..Default::default()
+            }
+            .generate(&mut grid);
+            grid.neighborhood = Neighborhood::EightNoCornerCutting;
+            grid.wrap = Wrap::Torus;
+            let open: Vec<_> = (0..grid.height)
+                .flat_map(|y| (0..grid.width).map(move |x| (x, y)))
+                .filter(|&(x, y)| grid.get(x, y) == Some(Cell::Empty))
+                .collect();
+            grid.add_portal(open[0], open[open.len() - 1], 20).unwrap();
+            grid.add_portal(open[open.len() / 3], open[open.len() / 2], 5)
+                .unwrap();
+            let [start, end] = grid.endpoints().unwrap();
+
+            let mut dijkstra = Dijkstra::new(&grid, start, end);
+            while dijkstra.step(&grid) {}
+            let mut jps = JumpPointSearch::new(&grid, start, end);
+            while jps.step(&grid) {}
+
+            let path = jps.get_path();
+            let neighbors_ok = path
+                .windows(2)
+                .all(|pair| grid.neighbors(pair[0].0, pair[0].1).any(|n| n == pair[1]));
+            assert!(neighbors_ok, "seed {seed}: invalid move in path");
+            assert_eq!(
+                grid.path_cost(&path),
+                dijkstra.distances.get(end).unwrap(),
+                "seed {seed}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_no_path() {
+        let mut grid = Grid::new(3, 3);
+        grid.neighborhood = Neighborhood::EightNoCornerCutting;
+        grid.set(1, 0, Cell::Wall);
+        grid.set(1, 1, Cell::Wall);
+        grid.set(1, 2, Cell::Wall);
+        let mut jps = JumpPointSearch::new(&grid, (0, 0), (2, 2));
+        while jps.step(&grid) {}
+
+        assert!(jps.is_finished());
+        assert!(!jps.found_path());
+        assert!(jps.get_path().is_empty());
+    }
+}
@@ -1,7 +1,26 @@
+pub mod algorithms;
 pub mod astar;
 pub mod bfs;
+pub mod bidirectional;
 pub mod cellular_automata;
 pub mod dfs;
 pub mod dijkstra;
+pub mod dstar_lite;
 pub mod grid;
+pub mod hex;
+pub mod jps;
+pub mod map_file;
+pub mod map_generator;
+pub mod maze;
+pub mod movingai;
+pub mod node_map;
 pub mod pathfinding;
+pub mod race;
+pub mod search_space;
+pub mod timeline;
+
+pub use algorithms::{
+    find_path, find_path_to_nearest, find_paths_to_all, AlgorithmType, SearchOptions,
+};
+pub use pathfinding::{PathError, PathResult, PathfindingAlgorithm};
+pub use search_space::SearchSpace;
@@ -1,66 +1,68 @@
 use macroquad::prelude::*;
 use path_finding::{
-    astar::AStar,
-    bfs::Bfs,
-    cellular_automata::CellularAutomata,
-    dfs::Dfs,
-    dijkstra::Dijkstra,
-    grid::{Cell, Grid},
+    algorithms::{create_algorithm_to_nearest, AlgorithmType},
+    astar::Heuristic,
+    grid::{Cell, Direction, Grid, Terrain, STRAIGHT_COST},
+    hex::{self, HexOrientation},
+    map_file,
+    map_generator::{generators, GenerationSteps},
     pathfinding::{NodeState, PathfindingAlgorithm},
+    race::Race,
+    timeline::Timeline,
 };
 
 const CELL_SIZE: f32 = 20.0;
 const GRID_WIDTH: usize = 50;
 const GRID_HEIGHT: usize = 50;
 const STEP_DELAY: f32 = 0.01;
-const STATUS_BAR_HEIGHT: f32 = 50.0;
+const STATUS_BAR_HEIGHT: f32 = 75.0;
+const WEIGHTS: [f32; 4] = [1.0, 1.5, 2.0, 5.0];
+const NOTICE_SECONDS: f64 = 3.0;
+/// Seconds each phase of an animated map generation stays on screen
+const GENERATION_DELAY: f32 = 0.4;
+/// `Maze::braid` when loops are switched on
+const MAZE_BRAID: f32 = 0.3;
+/// Cost of stepping through a portal placed with P
+const PORTAL_COST: u32 = STRAIGHT_COST;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
-pub enum AlgorithmType {
-    Dijkstra,
-    AStar,
-    Bfs,
-    Dfs,
+enum Brush {
+    Wall,
+    Terrain(Terrain),
+    /// Paints one-way arrows, None erases them
+    OneWay(Option<Direction>),
 }
 
-impl AlgorithmType {
-    pub fn all() -> &'static [Self] {
-        &[Self::Dijkstra, Self::AStar, Self::Bfs, Self::Dfs]
-    }
-
-    pub fn name(&self) -> &'static str {
+impl Brush {
+    fn name(&self) -> &'static str {
         match self {
-            Self::Dijkstra => "Dijkstra",
-            Self::AStar => "A*",
-            Self::Bfs => "BFS",
-            Self::Dfs => "DFS",
+            Self::Wall => "Wall",
+            Self::Terrain(terrain) => terrain.name(),
+            Self::OneWay(Some(Direction::Up)) => "One-way up",
+            Self::OneWay(Some(Direction::Right)) => "One-way right",
+            Self::OneWay(Some(Direction::Down)) => "One-way down",
+            Self::OneWay(Some(Direction::Left)) => "One-way left",
+            Self::OneWay(None) => "Two-way",
         }
     }
 
-    pub fn next(&self) -> Self {
-        let all = Self::all();
-        let current_idx = all.iter().position(|&a| a == *self).unwrap_or(0);
-        let next_idx = (current_idx + 1) % all.len();
-        all[next_idx]
-    }
-}
-
-impl Default for AlgorithmType {
-    fn default() -> Self {
-        Self::AStar
-    }
-}
-
-fn create_algorithm(
-    algorithm_type: AlgorithmType,
-    start: (usize, usize),
-    end: (usize, usize),
-) -> Box<dyn PathfindingAlgorithm> {
-    match algorithm_type {
-        AlgorithmType::Dijkstra => Box::new(Dijkstra::new(start, end)),
-        AlgorithmType::AStar => Box::new(AStar::new(start, end)),
-        AlgorithmType::Bfs => Box::new(Bfs::new(start, end)),
-        AlgorithmType::Dfs => Box::new(Dfs::new(start, end)),
+    fn next(&self) -> Self {
+        let terrains = Terrain::all();
+        let directions = Direction::all();
+        match self {
+            Self::Wall => Self::Terrain(terrains[0]),
+            Self::Terrain(terrain) => {
+                let idx = terrains.iter().position(|t| t == terrain).unwrap_or(0);
+                terrains
+                    .get(idx + 1)
+                    .map_or(Self::OneWay(Some(directions[0])), |&t| Self::Terrain(t))
+            }
+            Self::OneWay(Some(direction)) => {
+                let idx = directions.iter().position(|d| d == direction).unwrap_or(0);
+                Self::OneWay(directions.get(idx + 1).copied())
+            }
+            Self::OneWay(None) => Self::Wall,
+        }
     }
 }
 
@@ -86,19 +88,103 @@ async fn main() {
     let mut app_state = AppState::Editing;
     let mut path_algo: Option<Box<dyn PathfindingAlgorithm>> = None;
     let mut current_algorithm = AlgorithmType::default();
+    let mut brush = Brush::Wall;
+    let mut heuristic = Heuristic::default();
+    let mut weight_idx = 0;
     let mut step_timer = 0.0;
-    let mut cave_seed: u64 = 0;
+    let mut map_seed: u64 = 0;
+    let mut maze_loops = false;
+    let mut map_generators = generators(0.0);
+    // What G generates, cycled with M. `generators` lists the cave first
+    let mut generator_idx = 0;
+    // Map generation shown phase by phase, started with V
+    let mut generation: Option<Box<dyn GenerationSteps>> = None;
+    let mut generation_timer = 0.0;
     let mut first_run: bool = true;
+    let mut notice: Option<(String, f64)> = None;
+    let mut race_mode = false;
+    let mut race_lineup = vec![AlgorithmType::Dijkstra, AlgorithmType::AStar];
+    let mut race: Option<Race> = None;
+    let mut timeline: Option<Timeline> = None;
+    // First end of a portal being placed with P
+    let mut portal_start: Option<(usize, usize)> = None;
 
     loop {
         if is_key_pressed(KeyCode::Tab) {
             if let AppState::Editing = app_state {
-                current_algorithm = current_algorithm.next();
+                current_algorithm = current_algorithm.next_on(&grid);
+            }
+        }
+
+        if is_key_pressed(KeyCode::H) {
+            if let AppState::Editing = app_state {
+                heuristic = heuristic.next();
+            }
+        }
+
+        if is_key_pressed(KeyCode::W) {
+            if let AppState::Editing = app_state {
+                weight_idx = (weight_idx + 1) % WEIGHTS.len();
+            }
+        }
+
+        if is_key_pressed(KeyCode::N) {
+            if let AppState::Editing = app_state {
+                grid.neighborhood = grid.neighborhood.next();
             }
         }
 
-        if is_mouse_button_pressed(MouseButton::Left) {
-            if let Some((x, y)) = mouse_to_grid(&grid) {
+        if is_key_pressed(KeyCode::X) {
+            if let AppState::Editing = app_state {
+                grid.wrap = grid.wrap.next();
+            }
+        }
+
+        if is_key_pressed(KeyCode::R) {
+            if let AppState::Editing = app_state {
+                race_mode = !race_mode;
+                race = None;
+                path_algo = None;
+            }
+        }
+
+        if is_key_pressed(KeyCode::Enter) && race_mode {
+            if let AppState::Editing = app_state {
+                if let Some(idx) = race_lineup.iter().position(|&a| a == current_algorithm) {
+                    race_lineup.remove(idx);
+                } else if race_lineup.len() < Race::MAX_RACERS {
+                    race_lineup.push(current_algorithm);
+                }
+            }
+        }
+
+        if is_key_pressed(KeyCode::T) {
+            brush = brush.next();
+        }
+
+        // The race panes are drawn at a different scale, edits are made on
+        // the full-size grid before the race starts
+        let mouse_cell = if race.is_none() {
+            mouse_to_grid(&grid)
+        } else {
+            None
+        };
+
+        if let Brush::Terrain(_) | Brush::OneWay(_) = brush {
+            if is_mouse_button_down(MouseButton::Left) {
+                if let Some((x, y)) = mouse_cell {
+                    if grid.get(x, y) == Some(Cell::Wall) {
+                        grid.set(x, y, Cell::Empty);
+                    }
+                    match brush {
+                        Brush::Terrain(terrain) => grid.set_terrain(x, y, terrain),
+                        Brush::OneWay(direction) => grid.set_one_way(x, y, direction),
+                        Brush::Wall => {}
+                    }
+                }
+            }
+        } else if is_mouse_button_pressed(MouseButton::Left) {
+            if let Some((x, y)) = mouse_cell {
                 let current = grid.get(x, y).unwrap_or(Cell::Empty);
                 let new_cell = if current == Cell::Wall {
                     Cell::Empty
@@ -109,18 +195,41 @@ async fn main() {
             }
         }
 
+        // Like the wrap, portals are placed while editing. Searches that
+        // bake them into their heuristics start over when they change
+        if is_key_pressed(KeyCode::P) {
+            if let (AppState::Editing, Some(pos)) = (&app_state, mouse_cell) {
+                match portal_start.take() {
+                    Some(start) => {
+                        if let Err(e) = grid.add_portal(start, pos, PORTAL_COST) {
+                            notice = Some((format!("Can't place portal: {e}"), get_time()));
+                        }
+                    }
+                    // P on a portal removes it, anywhere else starts a new one
+                    None => {
+                        if grid.remove_portal(pos).is_none() {
+                            portal_start = Some(pos);
+                        }
+                    }
+                }
+            }
+        }
+
         if is_mouse_button_pressed(MouseButton::Right) {
-            if let Some((x, y)) = mouse_to_grid(&grid) {
+            if let Some((x, y)) = mouse_cell {
                 let current = grid.get(x, y).unwrap_or(Cell::Empty);
-                let (start, end) = find_start_end(&grid);
-
-                match (start, end) {
-                    (None, None) if current == Cell::Empty => {
+                // Once there is a start every click adds another end, clicking
+                // an end removes it again
+                match (grid.find(Cell::Start), current) {
+                    (None, Cell::Empty) => {
                         grid.set(x, y, Cell::Start);
                     }
-                    (Some(_), None) if current == Cell::Empty => {
+                    (Some(_), Cell::Empty) => {
                         grid.set(x, y, Cell::End);
                     }
+                    (_, Cell::End) => {
+                        grid.set(x, y, Cell::Empty);
+                    }
                     _ => {
                         // do nothing
                     }
@@ -128,44 +237,202 @@ async fn main() {
             }
         }
 
-        if is_key_pressed(KeyCode::Space) {
+        // Moves or terrain may have changed to ones JPS can't search
+        if !current_algorithm.supports(&grid) {
+            current_algorithm = AlgorithmType::default();
+        }
+        race_lineup.retain(|algorithm| algorithm.supports(&grid));
+
+        let changes = grid.take_changes();
+        if !changes.is_empty() && matches!(app_state, AppState::Running | AppState::Finished) {
+            let repaired = path_algo
+                .as_mut()
+                .is_some_and(|algo| algo.notify_changes(&grid, &changes));
+            if !repaired {
+                // Algorithms that can't repair their search start over
+                path_algo = match grid.start_and_goals() {
+                    Ok((s, goals)) => Some(create_algorithm_to_nearest(
+                        current_algorithm,
+                        &grid,
+                        s,
+                        &goals,
+                        &heuristic,
+                        WEIGHTS[weight_idx],
+                    )),
+                    Err(e) => {
+                        notice = Some((format!("Search stopped: {e}"), get_time()));
+                        None
+                    }
+                };
+            }
+            app_state = if path_algo.is_some() {
+                AppState::Running
+            } else {
+                AppState::Editing
+            };
+        }
+
+        if is_key_pressed(KeyCode::Space) && generation.is_some() {
+            // Skip the rest of the animation
+            if let Some(mut steps) = generation.take() {
+                while steps.step(&mut grid) {}
+            }
+        } else if is_key_pressed(KeyCode::Space) {
             match app_state {
-                AppState::Editing => {
-                    let (start, end) = find_start_end(&grid);
-                    if let (Some(s), Some(e)) = (start, end) {
-                        path_algo = Some(create_algorithm(current_algorithm, s, e));
+                AppState::Editing if race_mode => {
+                    if race_lineup.len() < Race::MIN_RACERS {
+                        let message = "Pick 2 to 4 algorithms with Tab and Enter".to_owned();
+                        notice = Some((message, get_time()));
+                    } else {
+                        match grid.start_and_goals() {
+                            Ok((s, goals)) => {
+                                race = Some(Race::new(
+                                    &grid,
+                                    &race_lineup,
+                                    s,
+                                    &goals,
+                                    &heuristic,
+                                    WEIGHTS[weight_idx],
+                                ));
+                                app_state = AppState::Running;
+                                step_timer = 0.0;
+                            }
+                            Err(e) => notice = Some((format!("Can't race: {e}"), get_time())),
+                        }
+                    }
+                }
+                AppState::Editing => match grid.start_and_goals() {
+                    Ok((s, goals)) => {
+                        path_algo = Some(create_algorithm_to_nearest(
+                            current_algorithm,
+                            &grid,
+                            s,
+                            &goals,
+                            &heuristic,
+                            WEIGHTS[weight_idx],
+                        ));
                         app_state = AppState::Running;
                         step_timer = 0.0;
                     }
-                }
+                    Err(e) => notice = Some((format!("Can't search: {e}"), get_time())),
+                },
                 AppState::Running => {
                     app_state = AppState::Editing;
                 }
                 AppState::Finished => {
                     path_algo = None;
+                    race = None;
                     app_state = AppState::Editing;
                 }
             }
         }
 
-        if is_key_pressed(KeyCode::G) || first_run {
-            cave_seed += 1;
+        if is_key_pressed(KeyCode::S) {
+            notice = Some((storage::save(&map_file::to_text(&grid)), get_time()));
+        }
+
+        let loaded = if is_key_pressed(KeyCode::O) {
+            storage::load()
+        } else {
+            storage::poll_upload()
+        };
+        if let Some(result) = loaded {
+            let message = match result.and_then(|text| load_map(&mut grid, &text)) {
+                Ok(()) => "Map loaded".to_owned(),
+                Err(e) => format!("Load failed: {e}"),
+            };
+            notice = Some((message, get_time()));
             path_algo = None;
+            race = None;
+            generation = None;
             app_state = AppState::Editing;
+        }
 
-            let generate = CellularAutomata {
-                seed: cave_seed,
-                ..Default::default()
-            };
-            generate.generate(&mut grid);
+        let switch_map = is_key_pressed(KeyCode::M);
+        if switch_map {
+            generator_idx = (generator_idx + 1) % map_generators.len();
+        }
+        let switch_loops = is_key_pressed(KeyCode::B) && generator_idx != 0;
+        if switch_loops {
+            maze_loops = !maze_loops;
+            map_generators = generators(if maze_loops { MAZE_BRAID } else { 0.0 });
+        }
+
+        let animate = is_key_pressed(KeyCode::V);
+        if is_key_pressed(KeyCode::G) || animate || switch_map || switch_loops || first_run {
+            map_seed += 1;
+            path_algo = None;
+            race = None;
+            generation = None;
+            app_state = AppState::Editing;
+
+            let generator = &mut map_generators[generator_idx];
+            generator.set_seed(map_seed);
+            match generator.steps().filter(|_| animate) {
+                Some(mut steps) => {
+                    if steps.step(&mut grid) {
+                        generation = Some(steps);
+                        generation_timer = 0.0;
+                    }
+                }
+                None => generator.generate(&mut grid),
+            }
             first_run = false;
         }
 
+        if let Some(ref mut steps) = generation {
+            generation_timer += get_frame_time();
+            if generation_timer >= GENERATION_DELAY {
+                generation_timer = 0.0;
+                if !steps.step(&mut grid) {
+                    generation = None;
+                }
+            }
+        }
+
+        // Replaying is possible while a single search is paused or finished
+        let replayable = race.is_none() && !matches!(app_state, AppState::Running);
+        if let (Some(d), true) = (&mut path_algo, replayable) {
+            if is_key_pressed(KeyCode::Left) {
+                timeline
+                    .get_or_insert_with(|| Timeline::new(d.events()))
+                    .step_back();
+            }
+
+            if is_key_pressed(KeyCode::Right) {
+                match timeline {
+                    Some(ref mut t) if !t.is_at_end() => t.step_forward(),
+                    _ => {
+                        // Past the end of the log, step the search itself
+                        timeline = None;
+                        if !d.is_finished() && !d.step(&grid) {
+                            app_state = AppState::Finished;
+                        }
+                    }
+                }
+            }
+
+            if is_mouse_button_down(MouseButton::Left) {
+                if let Some(fraction) = mouse_to_timeline() {
+                    let t = timeline.get_or_insert_with(|| Timeline::new(d.events()));
+                    let span = (t.last_step() - t.first_step()) as f32;
+                    t.seek(t.first_step() + (fraction * span).round() as usize);
+                }
+            }
+        } else {
+            timeline = None;
+        }
+
         if let AppState::Running = app_state {
             step_timer += get_frame_time();
             while step_timer >= STEP_DELAY {
                 step_timer -= STEP_DELAY;
-                if let Some(ref mut d) = path_algo {
+                if let Some(ref mut race) = race {
+                    if !race.step() {
+                        app_state = AppState::Finished;
+                        break;
+                    }
+                } else if let Some(ref mut d) = path_algo {
                     if !d.step(&grid) {
                         app_state = AppState::Finished;
                         break;
@@ -175,7 +442,38 @@ async fn main() {
         }
 
         clear_background(BLACK);
-        draw_grid(&grid, path_algo.as_deref());
+        if let Some(ref race) = race {
+            draw_race(race);
+        } else {
+            match (&timeline, &path_algo) {
+                (Some(t), _) => draw_grid(
+                    &grid,
+                    &|x, y| t.get_node_state((x, y)),
+                    (0.0, 0.0),
+                    CELL_SIZE,
+                ),
+                (None, Some(d)) => draw_grid(
+                    &grid,
+                    &|x, y| d.get_node_state((x, y)),
+                    (0.0, 0.0),
+                    CELL_SIZE,
+                ),
+                (None, None) => {
+                    draw_grid(&grid, &|_, _| NodeState::Unvisited, (0.0, 0.0), CELL_SIZE)
+                }
+            }
+            if let Some(ref steps) = generation {
+                draw_groups(&grid, steps.as_ref());
+            }
+            if let Some(pos) = portal_start {
+                let (cx, cy) = cell_center(&grid, pos, (0.0, 0.0), CELL_SIZE);
+                draw_circle_lines(cx, cy, CELL_SIZE / 2.0, 2.0, MAGENTA);
+            }
+            if let Some(ref d) = path_algo {
+                draw_stats(d.as_ref(), GRID_WIDTH as f32 * CELL_SIZE, 0.0);
+                draw_timeline(timeline.as_ref(), d.as_ref());
+            }
+        }
 
         let selector_y = GRID_HEIGHT as f32 * CELL_SIZE + 22.0;
         let mut x_offset = 10.0;
@@ -196,25 +494,99 @@ async fn main() {
                 );
             }
 
-            let color = if is_selected { WHITE } else { GRAY };
+            let color = if is_selected {
+                WHITE
+            } else if !algo_type.supports(&grid) {
+                DARKGRAY
+            } else if race_mode && race_lineup.contains(algo_type) {
+                GOLD
+            } else {
+                GRAY
+            };
             draw_text(name, x_offset, selector_y, 16.0, color);
 
             let text_width = measure_text(name, None, 16, 1.0).width;
             x_offset += text_width + 20.0;
         }
 
-        let status = match app_state {
-            AppState::Editing => &format!(
-                "Seed: {} | Tab: switch algorithm | G: new cave | SPACE: pathfind",
-                cave_seed
+        let heuristic_text = format!(
+            "Heuristic: {} (H) | Weight: {:.1} (W)",
+            heuristic.name(),
+            WEIGHTS[weight_idx]
+        );
+        let heuristic_color = if matches!(
+            current_algorithm,
+            AlgorithmType::AStar | AlgorithmType::BiAStar | AlgorithmType::DStarLite
+        ) {
+            WHITE
+        } else {
+            GRAY
+        };
+        draw_text(
+            &heuristic_text,
+            x_offset + 20.0,
+            selector_y,
+            16.0,
+            heuristic_color,
+        );
+
+        let replay_status = timeline.as_ref().map_or_else(String::new, |t| {
+            format!(
+                "Replay step {} of {} | Left/Right: step | drag the bar to scrub",
+                t.step(),
+                t.last_step()
+            )
+        });
+        let generation_status = generation.as_ref().map_or_else(String::new, |steps| {
+            format!(
+                "{}: {} | SPACE: skip",
+                map_generators[generator_idx].name(),
+                steps.phase()
+            )
+        });
+        let status = match (&notice, &app_state) {
+            (Some((message, shown_at)), _) if get_time() - shown_at < NOTICE_SECONDS => message,
+            (_, _) if generation.is_some() => &generation_status,
+            (_, AppState::Editing) if race_mode => &format!(
+                "Race: {} | Tab + Enter: pick 2-4 | R: single mode | G: new map | SPACE: race",
+                race_lineup
+                    .iter()
+                    .map(|a| a.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            (_, AppState::Editing) if portal_start.is_some() => {
+                "P on another cell: link the portal | P on the same cell: cancel"
+            }
+            (_, AppState::Editing) => &format!(
+                "Map: {} {} (M{}) | Brush: {} (T) | Moves: {} (N) | Wrap: {} (X) | P: portal | Tab: algorithm | R: race | G/V: new map/watch it build | S/O: save/load | SPACE: pathfind",
+                map_generators[generator_idx].name(),
+                map_generators[generator_idx].seed(),
+                match (generator_idx, maze_loops) {
+                    (0, _) => "",
+                    (_, true) => ", loops on (B)",
+                    (_, false) => ", loops off (B)",
+                },
+                brush.name(),
+                grid.neighborhood.name(),
+                grid.effective_wrap().name()
             ),
-            AppState::Running => "Running... SPACE to pause",
-            AppState::Finished => {
+            (_, _) if timeline.is_some() => &replay_status,
+            (_, AppState::Running) => "Running... SPACE to pause",
+            (_, AppState::Finished) if race.is_some() => &format!(
+                "{} | SPACE to reset",
+                race.iter()
+                    .flat_map(|race| race.standings())
+                    .map(|r| format!("{}. {}", r.place.unwrap_or(0), r.algorithm_type.name()))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+            ),
+            (_, AppState::Finished) => {
                 if let Some(ref d) = path_algo {
                     if d.found_path() {
-                        "Path found! SPACE to reset"
+                        "Path found! Left/Right or drag the bar to replay | SPACE to reset"
                     } else {
-                        "No path exists! SPACE to reset"
+                        "No path exists! Left/Right or drag the bar to replay | SPACE to reset"
                     }
                 } else {
                     "SPACE to reset"
@@ -227,26 +599,107 @@ async fn main() {
     }
 }
 
-fn draw_grid(grid: &Grid, path_algo: Option<&dyn PathfindingAlgorithm>) {
+/// Race panes in a 2x2 layout at half scale, the top row centered if
+/// there are only two racers
+fn draw_race(race: &Race) {
+    let cell_size = CELL_SIZE / 2.0;
+    let pane_width = GRID_WIDTH as f32 * cell_size;
+    let pane_height = GRID_HEIGHT as f32 * cell_size;
+    let top = if race.racers.len() <= 2 {
+        pane_height / 2.0
+    } else {
+        0.0
+    };
+
+    for (i, racer) in race.racers.iter().enumerate() {
+        let x = (i % 2) as f32 * pane_width;
+        let y = top + (i / 2) as f32 * pane_height;
+        draw_grid(
+            &racer.grid,
+            &|x, y| racer.algorithm.get_node_state((x, y)),
+            (x, y),
+            cell_size,
+        );
+        draw_stats(racer.algorithm.as_ref(), x + pane_width, y);
+
+        let label = match (racer.place, racer.finished_at) {
+            (Some(place), Some(step)) => format!(
+                "{}  #{} after {} steps",
+                racer.algorithm_type.name(),
+                place,
+                step
+            ),
+            _ => racer.algorithm_type.name().to_owned(),
+        };
+        let width = measure_text(&label, None, 16, 1.0).width;
+        draw_rectangle(
+            x + 10.0,
+            y + 10.0,
+            width + 16.0,
+            24.0,
+            Color::new(0.0, 0.0, 0.0, 0.7),
+        );
+        let color = if racer.place == Some(1) { GOLD } else { WHITE };
+        draw_text(&label, x + 18.0, y + 27.0, 16.0, color);
+    }
+}
+
+/// Live search counters in the top-right corner of a grid whose right edge
+/// is at `right`
+fn draw_stats(path_algo: &dyn PathfindingAlgorithm, right: f32, top: f32) {
+    let stats = path_algo.stats();
+    let path_cost = stats
+        .path_cost
+        .map_or_else(|| "-".to_owned(), |cost| cost.to_string());
+    let lines = [
+        format!("Expanded: {}", stats.nodes_expanded),
+        format!("Pushed: {}", stats.nodes_pushed),
+        format!("Max frontier: {}", stats.max_frontier),
+        format!("Duplicate pops: {}", stats.duplicate_pops),
+        format!("Steps: {}", stats.steps),
+        format!("Path cost: {}", path_cost),
+    ];
+
+    let width = 170.0;
+    let line_height = 18.0;
+    let x = right - width - 10.0;
+    let y = top + 10.0;
+    draw_rectangle(
+        x,
+        y,
+        width,
+        line_height * lines.len() as f32 + 10.0,
+        Color::new(0.0, 0.0, 0.0, 0.7),
+    );
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(line, x + 8.0, y + line_height * (i + 1) as f32, 16.0, WHITE);
+    }
+}
+
+fn draw_grid(
+    grid: &Grid,
+    node_state: &dyn Fn(usize, usize) -> NodeState,
+    (left, top): (f32, f32),
+    cell_size: f32,
+) {
     for y in 0..grid.height {
         for x in 0..grid.width {
             let base_color = match grid.get(x, y) {
-                Some(Cell::Empty) => DARKGRAY,
+                Some(Cell::Empty) => terrain_color(grid.get_terrain(x, y).unwrap_or_default()),
                 Some(Cell::Wall) => BLACK,
                 Some(Cell::Start) => GREEN,
                 Some(Cell::End) => RED,
                 None => DARKGRAY,
             };
 
-            let color = if let Some(d) = path_algo {
-                match d.get_node_state(x, y) {
-                    NodeState::Path => LIME,
-                    NodeState::Visited => SKYBLUE,
-                    NodeState::InQueue => YELLOW,
-                    NodeState::Unvisited => base_color,
-                }
-            } else {
-                base_color
+            let color = match node_state(x, y) {
+                NodeState::Path => LIME,
+                NodeState::Visited => SKYBLUE,
+                NodeState::InQueue => YELLOW,
+                NodeState::Scanned => Color::from_rgba(60, 90, 110, 255),
+                NodeState::BackwardVisited => VIOLET,
+                NodeState::BackwardInQueue => ORANGE,
+                NodeState::Unvisited => base_color,
             };
 
             let final_color = match grid.get(x, y) {
@@ -255,44 +708,260 @@ fn draw_grid(grid: &Grid, path_algo: Option<&dyn PathfindingAlgorithm>) {
                 _ => color,
             };
 
-            draw_rectangle(
-                x as f32 * CELL_SIZE,
-                y as f32 * CELL_SIZE,
-                CELL_SIZE - 1.0,
-                CELL_SIZE - 1.0,
-                final_color,
-            );
+            draw_cell(grid, (x, y), (left, top), cell_size, final_color);
+            if let Some(direction) = grid.get_one_way(x, y) {
+                draw_arrow(grid, (x, y), (left, top), cell_size, direction);
+            }
         }
     }
+    draw_portals(grid, (left, top), cell_size);
 }
 
-fn mouse_to_grid(grid: &Grid) -> Option<(usize, usize)> {
-    let (mx, my) = mouse_position();
-    let gx = (mx / CELL_SIZE) as usize;
-    let gy = (my / CELL_SIZE) as usize;
+/// Triangle pointing the way a one-way cell may be left
+fn draw_arrow(
+    grid: &Grid,
+    pos: (usize, usize),
+    origin: (f32, f32),
+    cell_size: f32,
+    direction: Direction,
+) {
+    let (cx, cy) = cell_center(grid, pos, origin, cell_size);
+    let (ux, uy) = direction.vector();
+    let (length, width) = (cell_size * 0.35, cell_size * 0.25);
+    let tip = vec2(cx + ux * length, cy + uy * length);
+    let back = vec2(cx - ux * length * 0.6, cy - uy * length * 0.6);
+    let side = vec2(-uy * width, ux * width);
+    draw_triangle(
+        tip,
+        back + side,
+        back - side,
+        Color::new(1.0, 1.0, 1.0, 0.7),
+    );
+}
 
-    if gx < grid.width && gy < grid.height {
-        Some((gx, gy))
-    } else {
-        None
+/// A line between the ends of each portal, with a ring on either end
+fn draw_portals(grid: &Grid, origin: (f32, f32), cell_size: f32) {
+    for portal in grid.portals() {
+        let (ax, ay) = cell_center(grid, portal.a, origin, cell_size);
+        let (bx, by) = cell_center(grid, portal.b, origin, cell_size);
+        draw_line(ax, ay, bx, by, 2.0, Color::new(1.0, 0.0, 1.0, 0.6));
+        for (x, y) in [(ax, ay), (bx, by)] {
+            draw_circle_lines(x, y, cell_size / 3.0, 2.0, MAGENTA);
+        }
     }
 }
 
-type Position = (usize, usize);
+fn cell_center(
+    grid: &Grid,
+    (x, y): (usize, usize),
+    (left, top): (f32, f32),
+    cell_size: f32,
+) -> (f32, f32) {
+    match grid.neighborhood.hex_orientation() {
+        Some(orientation) => {
+            let size = hex_size(grid, orientation, cell_size);
+            let (cx, cy) = hex::center(orientation, size, (x, y));
+            (left + cx, top + cy)
+        }
+        None => (
+            left + (x as f32 + 0.5) * cell_size,
+            top + (y as f32 + 0.5) * cell_size,
+        ),
+    }
+}
 
-fn find_start_end(grid: &Grid) -> (Option<Position>, Option<Position>) {
-    let mut start = None;
-    let mut end = None;
+/// Fill a square cell, or a hex on hex grids
+fn draw_cell(
+    grid: &Grid,
+    (x, y): (usize, usize),
+    (left, top): (f32, f32),
+    cell_size: f32,
+    color: Color,
+) {
+    match grid.neighborhood.hex_orientation() {
+        Some(orientation) => {
+            let size = hex_size(grid, orientation, cell_size);
+            let (cx, cy) = hex::center(orientation, size, (x, y));
+            let rotation = match orientation {
+                HexOrientation::Pointy => 30.0,
+                HexOrientation::Flat => 0.0,
+            };
+            draw_poly(left + cx, top + cy, 6, size - 0.5, rotation, color);
+        }
+        None => draw_rectangle(
+            left + x as f32 * cell_size,
+            top + y as f32 * cell_size,
+            cell_size - 1.0,
+            cell_size - 1.0,
+            color,
+        ),
+    }
+}
 
+/// Hexes as large as fit into the area the square cells would cover
+fn hex_size(grid: &Grid, orientation: HexOrientation, cell_size: f32) -> f32 {
+    let area = (
+        grid.width as f32 * cell_size,
+        grid.height as f32 * cell_size,
+    );
+    hex::fit_size(orientation, grid.width, grid.height, area)
+}
+
+/// Tint the cells of each group a generation phase reports, such as the
+/// regions of a cave before corridors join them
+fn draw_groups(grid: &Grid, steps: &dyn GenerationSteps) {
+    const COLORS: [Color; 6] = [PINK, GOLD, SKYBLUE, LIME, ORANGE, VIOLET];
     for y in 0..grid.height {
         for x in 0..grid.width {
-            match grid.get(x, y) {
-                Some(Cell::Start) => start = Some((x, y)),
-                Some(Cell::End) => end = Some((x, y)),
-                _ => {}
+            if let Some(group) = steps.group(x, y) {
+                let color = COLORS[group % COLORS.len()];
+                let tint = Color::new(color.r, color.g, color.b, 0.6);
+                draw_cell(grid, (x, y), (0.0, 0.0), CELL_SIZE, tint);
             }
         }
     }
+}
+
+/// Position and size of the timeline bar below the status line
+fn timeline_rect() -> Rect {
+    let y = GRID_HEIGHT as f32 * CELL_SIZE + 56.0;
+    Rect::new(10.0, y, GRID_WIDTH as f32 * CELL_SIZE - 20.0, 10.0)
+}
+
+/// Progress of the replay, or of the live search when not replaying
+fn draw_timeline(timeline: Option<&Timeline>, path_algo: &dyn PathfindingAlgorithm) {
+    let rect = timeline_rect();
+    let fraction = match timeline {
+        Some(t) if t.last_step() > t.first_step() => {
+            (t.step() - t.first_step()) as f32 / (t.last_step() - t.first_step()) as f32
+        }
+        _ => 1.0,
+    };
+    let color = if timeline.is_some() || path_algo.is_finished() {
+        WHITE
+    } else {
+        GRAY
+    };
+
+    draw_rectangle(rect.x, rect.y, rect.w, rect.h, DARKGRAY);
+    draw_rectangle(rect.x, rect.y, rect.w * fraction, rect.h, GRAY);
+    draw_rectangle(
+        rect.x + rect.w * fraction - 3.0,
+        rect.y - 3.0,
+        6.0,
+        rect.h + 6.0,
+        color,
+    );
+}
+
+/// Where along the timeline bar the mouse is, from 0.0 to 1.0
+fn mouse_to_timeline() -> Option<f32> {
+    let rect = timeline_rect();
+    let (mx, my) = mouse_position();
+    // Generous vertical margin so the thin bar is easy to grab
+    if mx < rect.x - 5.0
+        || mx > rect.x + rect.w + 5.0
+        || (my - (rect.y + rect.h / 2.0)).abs() > 10.0
+    {
+        return None;
+    }
+    Some(((mx - rect.x) / rect.w).clamp(0.0, 1.0))
+}
+
+fn load_map(grid: &mut Grid, text: &str) -> Result<(), String> {
+    let mut loaded = map_file::from_text(text).map_err(|e| e.to_string())?;
+    if loaded.width > GRID_WIDTH || loaded.height > GRID_HEIGHT {
+        return Err(format!(
+            "map is {}x{}, at most {}x{} fits the window",
+            loaded.width, loaded.height, GRID_WIDTH, GRID_HEIGHT
+        ));
+    }
+    loaded.neighborhood = grid.neighborhood;
+    loaded.wrap = grid.wrap;
+    *grid = loaded;
+    Ok(())
+}
+
+/// Maps are saved to a file next to the executable on desktop, and
+/// downloaded / picked with the browser's file dialog on the web build
+#[cfg(not(target_arch = "wasm32"))]
+mod storage {
+    const MAP_FILE: &str = "map.txt";
+
+    pub fn save(text: &str) -> String {
+        match std::fs::write(MAP_FILE, text) {
+            Ok(()) => format!("Saved {}", MAP_FILE),
+            Err(e) => format!("Saving {} failed: {}", MAP_FILE, e),
+        }
+    }
+
+    pub fn load() -> Option<Result<String, String>> {
+        Some(std::fs::read_to_string(MAP_FILE).map_err(|e| format!("{}: {}", MAP_FILE, e)))
+    }
+
+    pub fn poll_upload() -> Option<Result<String, String>> {
+        None
+    }
+}
 
-    (start, end)
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    // Implemented by the plugin in docs/index.html
+    extern "C" {
+        fn pf_download_map(ptr: *const u8, len: usize);
+        fn pf_request_upload();
+        fn pf_upload_len() -> usize;
+        fn pf_upload_take(ptr: *mut u8, len: usize);
+    }
+
+    pub fn save(text: &str) -> String {
+        unsafe { pf_download_map(text.as_ptr(), text.len()) };
+        "Downloading map.txt".to_owned()
+    }
+
+    /// Opens the file picker, the contents arrive later through `poll_upload`
+    pub fn load() -> Option<Result<String, String>> {
+        unsafe { pf_request_upload() };
+        None
+    }
+
+    pub fn poll_upload() -> Option<Result<String, String>> {
+        let len = unsafe { pf_upload_len() };
+        if len == 0 {
+            return None;
+        }
+        let mut bytes = vec![0u8; len];
+        unsafe { pf_upload_take(bytes.as_mut_ptr(), len) };
+        Some(String::from_utf8(bytes).map_err(|e| e.to_string()))
+    }
+}
+
+fn terrain_color(terrain: Terrain) -> Color {
+    match terrain {
+        Terrain::Grass => DARKGRAY,
+        Terrain::Sand => Color::from_rgba(150, 135, 90, 255),
+        Terrain::Mud => Color::from_rgba(90, 60, 35, 255),
+        Terrain::Water => Color::from_rgba(30, 60, 130, 255),
+    }
+}
+
+fn mouse_to_grid(grid: &Grid) -> Option<(usize, usize)> {
+    let (mx, my) = mouse_position();
+    let (gx, gy) = match grid.neighborhood.hex_orientation() {
+        Some(orientation) => {
+            let (hx, hy) = hex::hex_at(
+                orientation,
+                hex_size(grid, orientation, CELL_SIZE),
+                (mx, my),
+            );
+            (usize::try_from(hx).ok()?, usize::try_from(hy).ok()?)
+        }
+        None => ((mx / CELL_SIZE) as usize, (my / CELL_SIZE) as usize),
+    };
+
+    if gx < grid.width && gy < grid.height {
+        Some((gx, gy))
+    } else {
+        None
+    }
 }
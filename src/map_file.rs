@@ -0,0 +1,332 @@
+use crate::grid::{Cell, Direction, Grid, Terrain};
+use std::fmt;
+
+/// Error from parsing a map file, pointing at the offending character
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line of the offending character
+    pub line: usize,
+    /// 1-based column of the offending character
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    Empty,
+    UnknownSymbol(char),
+    RowLength {
+        expected: usize,
+        found: usize,
+    },
+    /// The one-way section has a different number of rows than the cells
+    RowCount {
+        expected: usize,
+        found: usize,
+    },
+    /// A required header line is missing or malformed
+    Header(&'static str),
+    InvalidNumber(String),
+    FieldCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match self.kind {
+            ParseErrorKind::Empty => write!(f, "map is empty"),
+            ParseErrorKind::UnknownSymbol(c) => write!(f, "unknown symbol '{c}'"),
+            ParseErrorKind::RowLength { expected, found } => {
+                write!(f, "row has {found} cells, expected {expected}")
+            }
+            ParseErrorKind::RowCount { expected, found } => {
+                write!(f, "section has {found} rows, expected {expected}")
+            }
+            ParseErrorKind::Header(name) => write!(f, "expected header '{name}'"),
+            ParseErrorKind::InvalidNumber(ref field) => write!(f, "'{field}' is not a number"),
+            ParseErrorKind::FieldCount { expected, found } => {
+                write!(f, "line has {found} fields, expected {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn symbol(cell: Cell, terrain: Terrain) -> char {
+    match (cell, terrain) {
+        (Cell::Wall, _) => '#',
+        (Cell::Start, _) => 'S',
+        (Cell::End, _) => 'E',
+        (Cell::Empty, Terrain::Grass) => '.',
+        (Cell::Empty, Terrain::Sand) => 's',
+        (Cell::Empty, Terrain::Mud) => 'm',
+        (Cell::Empty, Terrain::Water) => 'w',
+    }
+}
+
+fn parse_symbol(c: char) -> Option<(Cell, Terrain)> {
+    match c {
+        '#' => Some((Cell::Wall, Terrain::Grass)),
+        'S' => Some((Cell::Start, Terrain::Grass)),
+        'E' => Some((Cell::End, Terrain::Grass)),
+        '.' => Some((Cell::Empty, Terrain::Grass)),
+        's' => Some((Cell::Empty, Terrain::Sand)),
+        'm' => Some((Cell::Empty, Terrain::Mud)),
+        'w' => Some((Cell::Empty, Terrain::Water)),
+        _ => None,
+    }
+}
+
+fn arrow(one_way: Option<Direction>) -> char {
+    match one_way {
+        None => '.',
+        Some(Direction::Up) => '^',
+        Some(Direction::Right) => '>',
+        Some(Direction::Down) => 'v',
+        Some(Direction::Left) => '<',
+    }
+}
+
+/// `Some(None)` for a two-way cell, `None` for anything but an arrow or `.`
+fn parse_arrow(c: char) -> Option<Option<Direction>> {
+    match c {
+        '.' => Some(None),
+        '^' => Some(Some(Direction::Up)),
+        '>' => Some(Some(Direction::Right)),
+        'v' => Some(Some(Direction::Down)),
+        '<' => Some(Some(Direction::Left)),
+        _ => None,
+    }
+}
+
+/// Plain-text map format, one character per cell and one line per row:
+///
+/// ```text
+/// #########
+/// #S..ss..#
+/// #.##mm#.#
+/// #..www.E#
+/// #########
+///
+/// .........
+/// ....>>...
+/// .........
+/// .........
+/// .........
+/// ```
+///
+/// `#` wall, `.` grass, `s` sand, `m` mud, `w` water, `S` start, `E` end.
+/// Start and end are stored on grass. Grids with one-way cells get a
+/// second block of the same size after a blank line, with `^` `>` `v` `<`
+/// for one-way cells and `.` for the rest.
+pub fn to_text(grid: &Grid) -> String {
+    let mut text = String::with_capacity((grid.width + 1) * grid.height);
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let cell = grid.get(x, y).unwrap_or(Cell::Empty);
+            let terrain = grid.get_terrain(x, y).unwrap_or_default();
+            text.push(symbol(cell, terrain));
+        }
+        text.push('\n');
+    }
+    if grid.has_one_way_cells() {
+        text.push('\n');
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                text.push(arrow(grid.get_one_way(x, y)));
+            }
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// Error unless `row`, found on 1-based line `line`, has `width` characters
+fn check_width(row: &str, width: usize, line: usize) -> Result<(), ParseError> {
+    let found = row.chars().count();
+    if found == width {
+        return Ok(());
+    }
+    Err(ParseError {
+        line,
+        column: found.min(width) + 1,
+        kind: ParseErrorKind::RowLength {
+            expected: width,
+            found,
+        },
+    })
+}
+
+/// Parses the format written by `to_text`. Trailing blank lines and `\r\n`
+/// line endings are accepted
+pub fn from_text(text: &str) -> Result<Grid, ParseError> {
+    let rows: Vec<&str> = text
+        .trim_end()
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .collect();
+    let (cell_rows, one_way_rows) = match rows.iter().position(|row| row.is_empty()) {
+        Some(blank) => (&rows[..blank], Some((blank + 1, &rows[blank + 1..]))),
+        None => (&rows[..], None),
+    };
+
+    let width = cell_rows.first().map_or(0, |row| row.chars().count());
+    if width == 0 {
+        return Err(ParseError {
+            line: 1,
+            column: 1,
+            kind: ParseErrorKind::Empty,
+        });
+    }
+
+    let mut grid = Grid::new(width, cell_rows.len());
+    for (y, row) in cell_rows.iter().enumerate() {
+        check_width(row, width, y + 1)?;
+        for (x, c) in row.chars().enumerate() {
+            let Some((cell, terrain)) = parse_symbol(c) else {
+                return Err(ParseError {
+                    line: y + 1,
+                    column: x + 1,
+                    kind: ParseErrorKind::UnknownSymbol(c),
+                });
+            };
+            grid.set(x, y, cell);
+            grid.set_terrain(x, y, terrain);
+        }
+    }
+
+    if let Some((offset, one_way_rows)) = one_way_rows {
+        if one_way_rows.len() != grid.height {
+            return Err(ParseError {
+                line: offset + one_way_rows.len().min(grid.height) + 1,
+                column: 1,
+                kind: ParseErrorKind::RowCount {
+                    expected: grid.height,
+                    found: one_way_rows.len(),
+                },
+            });
+        }
+        for (y, row) in one_way_rows.iter().enumerate() {
+            let line = offset + y + 1;
+            check_width(row, width, line)?;
+            for (x, c) in row.chars().enumerate() {
+                let Some(one_way) = parse_arrow(c) else {
+                    return Err(ParseError {
+                        line,
+                        column: x + 1,
+                        kind: ParseErrorKind::UnknownSymbol(c),
+                    });
+                };
+                grid.set_one_way(x, y, one_way);
+            }
+        }
+    }
+    grid.take_changes();
+
+    Ok(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+#######
+#S.ss.#
+#.#mm.#
+#.www.#
+#....E#
+#######
+
+.......
+.......
+.......
+..<....
+..>>v..
+.......
+";
+
+    #[test]
+    fn test_round_trip() {
+        let grid = from_text(MAP).unwrap();
+        assert_eq!(grid.width, 7);
+        assert_eq!(grid.height, 6);
+        assert_eq!(grid.get(1, 1), Some(Cell::Start));
+        assert_eq!(grid.get(5, 4), Some(Cell::End));
+        assert_eq!(grid.get(2, 2), Some(Cell::Wall));
+        assert_eq!(grid.get_terrain(3, 3), Some(Terrain::Water));
+        assert_eq!(grid.get_one_way(4, 4), Some(Direction::Down));
+        assert_eq!(to_text(&grid), MAP);
+    }
+
+    #[test]
+    fn test_one_way_keeps_terrain() {
+        let mut grid = from_text("..\n..\n").unwrap();
+        grid.set_terrain(1, 0, Terrain::Mud);
+        grid.set_one_way(1, 0, Some(Direction::Right));
+        grid.set_one_way(0, 1, Some(Direction::Up));
+
+        let loaded = from_text(&to_text(&grid)).unwrap();
+        assert_eq!(loaded.get_terrain(1, 0), Some(Terrain::Mud));
+        assert_eq!(loaded.get_one_way(1, 0), Some(Direction::Right));
+        assert_eq!(loaded.get_one_way(0, 1), Some(Direction::Up));
+        assert_eq!(to_text(&loaded), to_text(&grid));
+        assert!(!from_text("..\n..\n").unwrap().has_one_way_cells());
+    }
+
+    #[test]
+    fn test_one_way_section_errors() {
+        let err = from_text("...\n...\n\n.x.\n...\n").unwrap_err();
+        assert_eq!((err.line, err.column), (4, 2));
+        assert_eq!(err.kind, ParseErrorKind::UnknownSymbol('x'));
+
+        let err = from_text("...\n...\n\n...\n").unwrap_err();
+        assert_eq!(err.line, 5);
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::RowCount {
+                expected: 2,
+                found: 1
+            }
+        );
+        let err = from_text("...\n...\n\n...\n...\n...\n").unwrap_err();
+        assert_eq!(err.line, 6);
+    }
+
+    #[test]
+    fn test_windows_line_endings() {
+        let grid = from_text("S.\r\n.E\r\n\r\n").unwrap();
+        assert_eq!((grid.width, grid.height), (2, 2));
+        assert_eq!(grid.get(1, 1), Some(Cell::End));
+    }
+
+    #[test]
+    fn test_unknown_symbol_position() {
+        let err = from_text("...\n.x.\n...").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 2));
+        assert_eq!(err.kind, ParseErrorKind::UnknownSymbol('x'));
+        assert_eq!(err.to_string(), "line 2, column 2: unknown symbol 'x'");
+    }
+
+    #[test]
+    fn test_ragged_rows() {
+        let err = from_text("...\n..\n...").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 3));
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::RowLength {
+                expected: 3,
+                found: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(from_text("\n\n").unwrap_err().kind, ParseErrorKind::Empty);
+    }
+}
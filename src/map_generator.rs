@@ -0,0 +1,81 @@
+use crate::cellular_automata::CellularAutomata;
+use crate::grid::Grid;
+use crate::maze::{Maze, MazeAlgorithm};
+
+/// Common interface for everything that builds maps
+pub trait MapGenerator {
+    /// Replace every cell and terrain of the grid with a new map, including
+    /// a start and an end. One-way cells and portals of the old map are
+    /// removed
+    fn generate(&self, grid: &mut Grid);
+
+    /// Start a generation that can be shown phase by phase, `None` if the
+    /// generator only works in one go
+    fn steps(&self) -> Option<Box<dyn GenerationSteps>> {
+        None
+    }
+
+    /// Get the generator's display name for the UI
+    fn name(&self) -> &'static str;
+
+    fn seed(&self) -> u64;
+
+    fn set_seed(&mut self, seed: u64);
+
+    /// Settings other than the seed, for display
+    fn describe(&self) -> String;
+}
+
+/// A generation in progress, advanced one visible phase at a time. The grid
+/// holds the finished map once `step` returns false
+pub trait GenerationSteps {
+    /// Run the next phase, returns false when there are none left
+    fn step(&mut self, grid: &mut Grid) -> bool;
+
+    /// Describe the phase the last `step` ran, for display
+    fn phase(&self) -> String;
+
+    /// Group a cell belongs to in the current phase, such as the regions of
+    /// a cave, so they can be told apart on screen
+    fn group(&self, _x: usize, _y: usize) -> Option<usize> {
+        None
+    }
+}
+
+/// The built-in generators: the cave first, then one maze per
+/// `MazeAlgorithm` with the given `Maze::braid`
+pub fn generators(maze_braid: f32) -> Vec<Box<dyn MapGenerator>> {
+    let mut generators: Vec<Box<dyn MapGenerator>> = vec![Box::new(CellularAutomata::default())];
+    for &algorithm in MazeAlgorithm::all() {
+        generators.push(Box::new(Maze {
+            algorithm,
+            braid: maze_braid,
+            ..Default::default()
+        }));
+    }
+    generators
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Cell, Direction};
+
+    #[test]
+    fn test_generators() {
+        for mut generator in generators(0.5) {
+            generator.set_seed(9);
+            assert_eq!(generator.seed(), 9);
+
+            let mut grid = Grid::new(31, 21);
+            grid.set_one_way(5, 5, Some(Direction::Up));
+            grid.add_portal((1, 1), (9, 9), 10).unwrap();
+            generator.generate(&mut grid);
+            assert!(!grid.has_one_way_cells(), "{}", generator.name());
+            assert!(grid.portals().is_empty(), "{}", generator.name());
+            let [start, end] = grid.endpoints().unwrap();
+            assert_ne!(start, end, "{}", generator.name());
+            assert!(grid.cells.contains(&Cell::Wall), "{}", generator.name());
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::HashSet;
+
+use crate::grid::{Cell, Grid, Terrain};
+use crate::map_generator::MapGenerator;
+use rand::prelude::*;
+use rand::rngs::SmallRng;
+
+/// Ways of generating a perfect maze, one with exactly one path between any
+/// two of its cells
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MazeAlgorithm {
+    /// Depth-first random walk, long winding corridors with few branches
+    #[default]
+    RecursiveBacktracker,
+    /// Grows from a random frontier cell, many short dead ends
+    Prim,
+    /// Joins random walls between cells not connected yet
+    Kruskal,
+    /// Loop-erased random walks, an unbiased pick among all perfect mazes
+    Wilson,
+    /// Builds row by row, keeping track of which cells are connected
+    Eller,
+    /// Splits open chambers with walls that have a single gap
+    RecursiveDivision,
+}
+
+impl MazeAlgorithm {
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::RecursiveBacktracker,
+            Self::Prim,
+            Self::Kruskal,
+            Self::Wilson,
+            Self::Eller,
+            Self::RecursiveDivision,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::RecursiveBacktracker => "Backtracker",
+            Self::Prim => "Prim",
+            Self::Kruskal => "Kruskal",
+            Self::Wilson => "Wilson",
+            Self::Eller => "Eller",
+            Self::RecursiveDivision => "Division",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let all = Self::all();
+        let current_idx = all.iter().position(|&a| a == *self).unwrap_or(0);
+        all[(current_idx + 1) % all.len()]
+    }
+}
+
+/// Maze cells sit on odd grid coordinates with a wall cell between each pair
+/// of neighbors, so a maze of `cols` x `rows` cells fills a grid of
+/// `2 * cols + 1` x `2 * rows + 1`. Leftover rows and columns stay walls.
+///
+/// The start is placed in the top left cell and the end in the bottom right.
+pub struct Maze {
+    pub algorithm: MazeAlgorithm,
+    pub seed: u64,
+    /// Chance of knocking a wall out of each dead end, adding loops.
+    /// 0 keeps the maze perfect, 1 removes every dead end
+    pub braid: f32,
+}
+
+impl Default for Maze {
+    fn default() -> Self {
+        Self {
+            algorithm: MazeAlgorithm::default(),
+            seed: 12345,
+            braid: 0.0,
+        }
+    }
+}
+
+type MazeCell = (usize, usize);
+
+impl MapGenerator for Maze {
+    fn generate(&self, grid: &mut Grid) {
+        let mut rng = SmallRng::seed_from_u64(self.seed);
+        let cols = grid.width.saturating_sub(1) / 2;
+        let rows = grid.height.saturating_sub(1) / 2;
+
+        let open_interior = self.algorithm == MazeAlgorithm::RecursiveDivision;
+        grid.clear_portals();
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                grid.set_terrain(x, y, Terrain::default());
+                grid.set_one_way(x, y, None);
+                let inside = x >= 1 && y >= 1 && x < 2 * cols && y < 2 * rows;
+                let cell = if open_interior && inside {
+                    Cell::Empty
+                } else {
+                    Cell::Wall
+                };
+                grid.set(x, y, cell);
+            }
+        }
+
+        if cols == 0 || rows == 0 {
+            return;
+        }
+
+        match self.algorithm {
+            MazeAlgorithm::RecursiveBacktracker => backtracker(grid, cols, rows, &mut rng),
+            MazeAlgorithm::Prim => prim(grid, cols, rows, &mut rng),
+            MazeAlgorithm::Kruskal => kruskal(grid, cols, rows, &mut rng),
+            MazeAlgorithm::Wilson => wilson(grid, cols, rows, &mut rng),
+            MazeAlgorithm::Eller => eller(grid, cols, rows, &mut rng),
+            MazeAlgorithm::RecursiveDivision => divide(grid, (0, 0), cols, rows, &mut rng),
+        }
+
+        if self.braid > 0.0 {
+            braid(grid, cols, rows, self.braid.min(1.0) as f64, &mut rng);
+        }
+
+        grid.set(1, 1, Cell::Start);
+        if cols * rows > 1 {
+            grid.set(2 * cols - 1, 2 * rows - 1, Cell::End);
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self.algorithm {
+            MazeAlgorithm::RecursiveBacktracker => "Backtracker maze",
+            MazeAlgorithm::Prim => "Prim maze",
+            MazeAlgorithm::Kruskal => "Kruskal maze",
+            MazeAlgorithm::Wilson => "Wilson maze",
+            MazeAlgorithm::Eller => "Eller maze",
+            MazeAlgorithm::RecursiveDivision => "Division maze",
+        }
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn describe(&self) -> String {
+        if self.braid > 0.0 {
+            format!("{:.0}% of dead ends opened", self.braid.min(1.0) * 100.0)
+        } else {
+            "perfect".to_owned()
+        }
+    }
+}
+
+fn open(grid: &mut Grid, (x, y): MazeCell) {
+    grid.set(2 * x + 1, 2 * y + 1, Cell::Empty);
+}
+
+/// Opens both cells and the wall between them
+fn carve(grid: &mut Grid, a: MazeCell, b: MazeCell) {
+    open(grid, a);
+    open(grid, b);
+    grid.set(a.0 + b.0 + 1, a.1 + b.1 + 1, Cell::Empty);
+}
+
+fn is_linked(grid: &Grid, a: MazeCell, b: MazeCell) -> bool {
+    grid.get(a.0 + b.0 + 1, a.1 + b.1 + 1) != Some(Cell::Wall)
+}
+
+fn cell_neighbors(cols: usize, rows: usize, (x, y): MazeCell) -> impl Iterator<Item = MazeCell> {
+    [(0, -1), (-1, 0), (1, 0), (0, 1)]
+        .into_iter()
+        .filter_map(move |(dx, dy)| {
+            let nx = x.checked_add_signed(dx)?;
+            let ny = y.checked_add_signed(dy)?;
+            (nx < cols && ny < rows).then_some((nx, ny))
+        })
+}
+
+fn random_cell(cols: usize, rows: usize, rng: &mut SmallRng) -> MazeCell {
+    (rng.gen_range(0..cols), rng.gen_range(0..rows))
+}
+
+fn backtracker(grid: &mut Grid, cols: usize, rows: usize, rng: &mut SmallRng) {
+    let mut visited = vec![false; cols * rows];
+    let start = random_cell(cols, rows, rng);
+    visited[start.1 * cols + start.0] = true;
+    open(grid, start);
+
+    let mut stack = vec![start];
+    while let Some(&current) = stack.last() {
+        let next = cell_neighbors(cols, rows, current)
+            .filter(|&(x, y)| !visited[y * cols + x])
+            .choose(rng);
+        match next {
+            Some(next) => {
+                visited[next.1 * cols + next.0] = true;
+                carve(grid, current, next);
+                stack.push(next);
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+}
+
+fn prim(grid: &mut Grid, cols: usize, rows: usize, rng: &mut SmallRng) {
+    let mut in_maze = vec![false; cols * rows];
+    let start = random_cell(cols, rows, rng);
+    in_maze[start.1 * cols + start.0] = true;
+    open(grid, start);
+
+    let mut frontier: Vec<(MazeCell, MazeCell)> = cell_neighbors(cols, rows, start)
+        .map(|next| (start, next))
+        .collect();
+    while !frontier.is_empty() {
+        let (from, to) = frontier.swap_remove(rng.gen_range(0..frontier.len()));
+        if in_maze[to.1 * cols + to.0] {
+            continue;
+        }
+        in_maze[to.1 * cols + to.0] = true;
+        carve(grid, from, to);
+        frontier.extend(
+            cell_neighbors(cols, rows, to)
+                .filter(|&(x, y)| !in_maze[y * cols + x])
+                .map(|next| (to, next)),
+        );
+    }
+}
+
+fn kruskal(grid: &mut Grid, cols: usize, rows: usize, rng: &mut SmallRng) {
+    let mut walls = Vec::new();
+    for y in 0..rows {
+        for x in 0..cols {
+            if x + 1 < cols {
+                walls.push(((x, y), (x + 1, y)));
+            }
+            if y + 1 < rows {
+                walls.push(((x, y), (x, y + 1)));
+            }
+        }
+    }
+    walls.shuffle(rng);
+
+    // Union-find over cell indices
+    let mut parents: Vec<usize> = (0..cols * rows).collect();
+    fn root(parents: &mut [usize], mut idx: usize) -> usize {
+        while parents[idx] != idx {
+            parents[idx] = parents[parents[idx]];
+            idx = parents[idx];
+        }
+        idx
+    }
+
+    for (a, b) in walls {
+        let root_a = root(&mut parents, a.1 * cols + a.0);
+        let root_b = root(&mut parents, b.1 * cols + b.0);
+        if root_a != root_b {
+            parents[root_a] = root_b;
+            carve(grid, a, b);
+        }
+    }
+}
+
+fn wilson(grid: &mut Grid, cols: usize, rows: usize, rng: &mut SmallRng) {
+    let mut in_maze = vec![false; cols * rows];
+    let first = random_cell(cols, rows, rng);
+    in_maze[first.1 * cols + first.0] = true;
+    open(grid, first);
+
+    // Where the walk last left each cell, overwriting erases loops
+    let mut exits = vec![(0, 0); cols * rows];
+    for y in 0..rows {
+        for x in 0..cols {
+            let mut current = (x, y);
+            while !in_maze[current.1 * cols + current.0] {
+                let next = cell_neighbors(cols, rows, current)
+                    .choose(rng)
+                    .expect("a maze of more than one cell");
+                exits[current.1 * cols + current.0] = next;
+                current = next;
+            }
+
+            let mut current = (x, y);
+            while !in_maze[current.1 * cols + current.0] {
+                in_maze[current.1 * cols + current.0] = true;
+                let next = exits[current.1 * cols + current.0];
+                carve(grid, current, next);
+                current = next;
+            }
+        }
+    }
+}
+
+fn eller(grid: &mut Grid, cols: usize, rows: usize, rng: &mut SmallRng) {
+    let mut sets: Vec<usize> = (0..cols).collect();
+    let mut next_set = cols;
+
+    for y in 0..rows {
+        let last_row = y + 1 == rows;
+        for x in 0..cols {
+            open(grid, (x, y));
+        }
+
+        for x in 0..cols - 1 {
+            if sets[x] != sets[x + 1] && (last_row || rng.gen_bool(0.5)) {
+                let (merged, kept) = (sets[x + 1], sets[x]);
+                sets.iter_mut()
+                    .filter(|set| **set == merged)
+                    .for_each(|set| *set = kept);
+                carve(grid, (x, y), (x + 1, y));
+            }
+        }
+
+        if last_row {
+            break;
+        }
+
+        // Every set continues down at least once so none gets cut off
+        let mut columns: Vec<usize> = (0..cols).collect();
+        columns.shuffle(rng);
+        let mut down = vec![false; cols];
+        let mut continued = HashSet::new();
+        for &x in &columns {
+            if rng.gen_bool(0.5) {
+                down[x] = true;
+                continued.insert(sets[x]);
+            }
+        }
+        for &x in &columns {
+            if continued.insert(sets[x]) {
+                down[x] = true;
+            }
+        }
+
+        for x in 0..cols {
+            if down[x] {
+                carve(grid, (x, y), (x, y + 1));
+            } else {
+                sets[x] = next_set;
+                next_set += 1;
+            }
+        }
+    }
+}
+
+/// Splits the chamber of `cols` x `rows` cells at `origin` in two with a
+/// wall that has one gap, then splits both halves the same way
+fn divide(grid: &mut Grid, origin: MazeCell, cols: usize, rows: usize, rng: &mut SmallRng) {
+    if cols < 2 || rows < 2 {
+        return;
+    }
+
+    let (x0, y0) = origin;
+    let horizontal = match cols.cmp(&rows) {
+        std::cmp::Ordering::Less => true,
+        std::cmp::Ordering::Greater => false,
+        std::cmp::Ordering::Equal => rng.gen_bool(0.5),
+    };
+
+    if horizontal {
+        let above = rng.gen_range(1..rows);
+        let gap = 2 * (x0 + rng.gen_range(0..cols)) + 1;
+        let wall_y = 2 * (y0 + above);
+        for x in (2 * x0 + 1..2 * (x0 + cols)).filter(|&x| x != gap) {
+            grid.set(x, wall_y, Cell::Wall);
+        }
+        divide(grid, origin, cols, above, rng);
+        divide(grid, (x0, y0 + above), cols, rows - above, rng);
+    } else {
+        let left = rng.gen_range(1..cols);
+        let gap = 2 * (y0 + rng.gen_range(0..rows)) + 1;
+        let wall_x = 2 * (x0 + left);
+        for y in (2 * y0 + 1..2 * (y0 + rows)).filter(|&y| y != gap) {
+            grid.set(wall_x, y, Cell::Wall);
+        }
+        divide(grid, origin, left, rows, rng);
+        divide(grid, (x0 + left, y0), cols - left, rows, rng);
+    }
+}
+
+/// Opens a random wall of each dead end with the given chance
+fn braid(grid: &mut Grid, cols: usize, rows: usize, chance: f64, rng: &mut SmallRng) {
+    for y in 0..rows {
+        for x in 0..cols {
+            let cell = (x, y);
+            let links = cell_neighbors(cols, rows, cell)
+                .filter(|&next| is_linked(grid, cell, next))
+                .count();
+            if links != 1 || !rng.gen_bool(chance) {
+                continue;
+            }
+
+            let wall = cell_neighbors(cols, rows, cell)
+                .filter(|&next| !is_linked(grid, cell, next))
+                .choose(rng);
+            if let Some(next) = wall {
+                carve(grid, cell, next);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Open cells reachable from the start, moving 4-way
+    fn reachable(grid: &Grid) -> usize {
+        let mut seen = HashSet::from([(1, 1)]);
+        let mut stack = vec![(1, 1)];
+        while let Some((x, y)) = stack.pop() {
+            for next in grid
+                .neighbors_in(x, y, crate::grid::Neighborhood::Four)
+                .passable()
+            {
+                if seen.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        seen.len()
+    }
+
+    fn open_cells(grid: &Grid) -> usize {
+        grid.cells.iter().filter(|&&c| c != Cell::Wall).count()
+    }
+
+    #[test]
+    fn test_perfect_mazes() {
+        for &algorithm in MazeAlgorithm::all() {
+            for seed in 0..5 {
+                let mut grid = Grid::new(21, 16);
+                Maze {
+                    algorithm,
+                    seed,
+                    ..Default::default()
+                }
+                .generate(&mut grid);
+
+                // 10x7 cells joined by the 69 passages of a spanning tree
+                let name = algorithm.name();
+                assert_eq!(open_cells(&grid), 70 + 69, "{name} seed {seed}");
+                assert_eq!(reachable(&grid), 70 + 69, "{name} seed {seed}");
+                assert_eq!(grid.get(1, 1), Some(Cell::Start));
+                assert_eq!(grid.get(19, 13), Some(Cell::End));
+                assert!((0..21).all(|x| grid.get(x, 14) == Some(Cell::Wall)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_braid_removes_dead_ends() {
+        let mut grid = Grid::new(21, 21);
+        Maze {
+            braid: 1.0,
+            ..Default::default()
+        }
+        .generate(&mut grid);
+
+        assert!(open_cells(&grid) > 100 + 99);
+        for y in 0..10 {
+            for x in 0..10 {
+                let links = cell_neighbors(10, 10, (x, y))
+                    .filter(|&next| is_linked(&grid, (x, y), next))
+                    .count();
+                assert!(links >= 2, "dead end at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_maze() {
+        for &algorithm in MazeAlgorithm::all() {
+            let generate = |seed| {
+                let mut grid = Grid::new(31, 31);
+                Maze {
+                    algorithm,
+                    seed,
+                    braid: 0.5,
+                }
+                .generate(&mut grid);
+                grid.cells
+            };
+            assert_eq!(generate(3), generate(3));
+            assert_ne!(generate(3), generate(4));
+        }
+    }
+}
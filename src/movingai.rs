@@ -0,0 +1,406 @@
+use crate::algorithms::{try_create_algorithm, AlgorithmType};
+use crate::astar::Heuristic;
+use crate::grid::{Cell, Grid, Neighborhood};
+use crate::map_file::{ParseError, ParseErrorKind};
+use crate::pathfinding::PathError;
+use std::fmt;
+
+/// Published optimal lengths are printed with 8 decimals
+const LENGTH_TOLERANCE: f64 = 1e-4;
+
+/// One line of a MovingAI `.scen` file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    pub bucket: u32,
+    pub map: String,
+    pub map_width: usize,
+    pub map_height: usize,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    /// Octile length of the shortest path, diagonals cost √2
+    pub optimal_length: f64,
+}
+
+/// Why a scenario produced no path
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScenarioError {
+    /// The scenario was made for a map of another width and height
+    MapSize((usize, usize)),
+    Path(PathError),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::MapSize((width, height)) => {
+                write!(f, "scenario is for a {width}x{height} map")
+            }
+            ScenarioError::Path(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+#[derive(Clone, Debug)]
+pub struct ScenarioResult {
+    pub scenario: usize,
+    pub algorithm: AlgorithmType,
+    /// Octile length of the path found
+    pub length: Result<f64, ScenarioError>,
+    pub optimal_length: f64,
+}
+
+impl ScenarioResult {
+    pub fn is_optimal(&self) -> bool {
+        self.length
+            .is_ok_and(|length| (length - self.optimal_length).abs() < LENGTH_TOLERANCE)
+    }
+}
+
+fn error(line: usize, column: usize, kind: ParseErrorKind) -> ParseError {
+    ParseError { line, column, kind }
+}
+
+type Fields<'a> = Vec<(usize, &'a str)>;
+
+/// Whitespace separated fields of a line with their 1-based columns
+fn fields(line: &str) -> Fields<'_> {
+    line.split_whitespace()
+        .map(|field| (field.as_ptr() as usize - line.as_ptr() as usize + 1, field))
+        .collect()
+}
+
+fn parse_number<T: std::str::FromStr>(
+    line: usize,
+    (column, field): (usize, &str),
+) -> Result<T, ParseError> {
+    field.parse().map_err(|_| {
+        error(
+            line,
+            column,
+            ParseErrorKind::InvalidNumber(field.to_owned()),
+        )
+    })
+}
+
+/// Next line as a `name` header, with one value if `value` is set
+fn header<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    name: &'static str,
+    value: bool,
+) -> Result<(usize, Fields<'a>), ParseError> {
+    let Some((idx, line)) = lines.next() else {
+        return Err(error(1, 1, ParseErrorKind::Header(name)));
+    };
+    let fields = fields(line);
+    match fields.first() {
+        Some(&(_, key)) if key == name && fields.len() == 1 + value as usize => {
+            Ok((idx + 1, fields))
+        }
+        _ => Err(error(idx + 1, 1, ParseErrorKind::Header(name))),
+    }
+}
+
+fn symbol(c: char) -> Option<Cell> {
+    match c {
+        '.' | 'G' | 'S' => Some(Cell::Empty),
+        '@' | 'O' | 'T' | 'W' => Some(Cell::Wall),
+        _ => None,
+    }
+}
+
+/// Parses a MovingAI `.map` file:
+///
+/// ```text
+/// type octile
+/// height 4
+/// width 6
+/// map
+/// @@@@@@
+/// @..T.@
+/// @.S.W@
+/// @@@@@@
+/// ```
+///
+/// `.`, `G` and `S` (swamp) are ground of uniform cost, `@`, `O` (out of
+/// bounds), `T` (trees) and `W` (water, which ground can't be entered from)
+/// become walls. The grid uses 8-way movement without corner cutting, the
+/// rule the benchmark lengths are computed with.
+pub fn parse_map(text: &str) -> Result<Grid, ParseError> {
+    let mut lines = text
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .enumerate();
+    let (line, fields) = header(&mut lines, "type", true)?;
+    if fields[1].1 != "octile" {
+        return Err(error(
+            line,
+            fields[1].0,
+            ParseErrorKind::Header("type octile"),
+        ));
+    }
+    let (line, fields) = header(&mut lines, "height", true)?;
+    let height: usize = parse_number(line, fields[1])?;
+    let (line, fields) = header(&mut lines, "width", true)?;
+    let width: usize = parse_number(line, fields[1])?;
+    header(&mut lines, "map", false)?;
+
+    if width == 0 || height == 0 {
+        return Err(error(1, 1, ParseErrorKind::Empty));
+    }
+
+    let mut grid = Grid::new(width, height);
+    grid.neighborhood = Neighborhood::EightNoCornerCutting;
+
+    for y in 0..height {
+        let Some((idx, row)) = lines.next() else {
+            let kind = ParseErrorKind::RowLength {
+                expected: width,
+                found: 0,
+            };
+            return Err(error(5 + y, 1, kind));
+        };
+        let found = row.chars().count();
+        if found != width {
+            return Err(error(
+                idx + 1,
+                found.min(width) + 1,
+                ParseErrorKind::RowLength {
+                    expected: width,
+                    found,
+                },
+            ));
+        }
+        for (x, c) in row.chars().enumerate() {
+            let cell =
+                symbol(c).ok_or_else(|| error(idx + 1, x + 1, ParseErrorKind::UnknownSymbol(c)))?;
+            grid.set(x, y, cell);
+        }
+    }
+    grid.take_changes();
+
+    Ok(grid)
+}
+
+/// Parses a MovingAI `.scen` file: a `version` line followed by one scenario
+/// per line, `bucket map width height start_x start_y goal_x goal_y optimal`
+pub fn parse_scenarios(text: &str) -> Result<Vec<Scenario>, ParseError> {
+    let mut scenarios = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        let fields = fields(line);
+
+        if idx == 0 {
+            match fields.as_slice() {
+                [(_, "version"), ..] => continue,
+                _ => return Err(error(line_no, 1, ParseErrorKind::Header("version"))),
+            }
+        }
+        if fields.is_empty() {
+            continue;
+        }
+        if fields.len() != 9 {
+            return Err(error(
+                line_no,
+                1,
+                ParseErrorKind::FieldCount {
+                    expected: 9,
+                    found: fields.len(),
+                },
+            ));
+        }
+
+        scenarios.push(Scenario {
+            bucket: parse_number(line_no, fields[0])?,
+            map: fields[1].1.to_owned(),
+            map_width: parse_number(line_no, fields[2])?,
+            map_height: parse_number(line_no, fields[3])?,
+            start: (
+                parse_number(line_no, fields[4])?,
+                parse_number(line_no, fields[5])?,
+            ),
+            end: (
+                parse_number(line_no, fields[6])?,
+                parse_number(line_no, fields[7])?,
+            ),
+            optimal_length: parse_number(line_no, fields[8])?,
+        });
+    }
+
+    Ok(scenarios)
+}
+
+/// Length of a path with straight steps counted as 1 and diagonals as √2
+pub fn octile_length(path: &[(usize, usize)]) -> f64 {
+    path.windows(2)
+        .map(|pair| {
+            let dx = pair[0].0.abs_diff(pair[1].0) as f64;
+            let dy = pair[0].1.abs_diff(pair[1].1) as f64;
+            (dx * dx + dy * dy).sqrt()
+        })
+        .sum()
+}
+
+/// Runs every algorithm to completion on every scenario. Algorithms in the A*
+/// family use the octile heuristic. Scenarios for a map of another size and
+/// endpoints `Grid::validate_endpoints` rejects give an error instead of a
+/// length.
+pub fn run_scenarios(
+    grid: &Grid,
+    scenarios: &[Scenario],
+    algorithms: &[AlgorithmType],
+) -> Vec<ScenarioResult> {
+    let mut results = Vec::new();
+
+    for (idx, scenario) in scenarios.iter().enumerate() {
+        for &algorithm_type in algorithms {
+            let size = (scenario.map_width, scenario.map_height);
+            let length = if size != (grid.width, grid.height) {
+                Err(ScenarioError::MapSize(size))
+            } else {
+                try_create_algorithm(
+                    algorithm_type,
+                    grid,
+                    scenario.start,
+                    scenario.end,
+                    &Heuristic::Octile,
+                    1.0,
+                )
+                .and_then(|mut algorithm| algorithm.run_to_end(grid).ok_or(PathError::NoPath))
+                .map(|result| octile_length(&result.path))
+                .map_err(ScenarioError::Path)
+            };
+
+            results.push(ScenarioResult {
+                scenario: idx,
+                algorithm: algorithm_type,
+                length,
+                optimal_length: scenario.optimal_length,
+            });
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+type octile
+height 5
+width 7
+map
+@@@@@@@
+@.....@
+@.....@
+@...T.@
+@@@@@@@
+";
+
+    const SCEN: &str = "version 1
+0\ttest.map\t7\t5\t1\t1\t5\t3\t5.41421356
+0\ttest.map\t7\t5\t1\t1\t3\t3\t2.82842712
+";
+
+    #[test]
+    fn test_parse_map() {
+        let grid = parse_map(MAP).unwrap();
+        assert_eq!((grid.width, grid.height), (7, 5));
+        assert_eq!(grid.neighborhood, Neighborhood::EightNoCornerCutting);
+        assert_eq!(grid.get(0, 0), Some(Cell::Wall));
+        assert_eq!(grid.get(4, 3), Some(Cell::Wall));
+        assert_eq!(grid.get(1, 1), Some(Cell::Empty));
+    }
+
+    #[test]
+    fn test_parse_map_errors() {
+        let err = parse_map("type octile\nheight x\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 8));
+        assert_eq!(err.kind, ParseErrorKind::InvalidNumber("x".to_owned()));
+
+        let err = parse_map(&MAP.replace("@...T.@", "@...X.@")).unwrap_err();
+        assert_eq!((err.line, err.column), (8, 5));
+        assert_eq!(err.kind, ParseErrorKind::UnknownSymbol('X'));
+
+        let err = parse_map("type octile\nwidth 4\n").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::Header("height"));
+    }
+
+    #[test]
+    fn test_parse_scenarios() {
+        let scenarios = parse_scenarios(SCEN).unwrap();
+        assert_eq!(scenarios.len(), 2);
+        assert_eq!(scenarios[0].start, (1, 1));
+        assert_eq!(scenarios[0].end, (5, 3));
+        assert!((scenarios[0].optimal_length - 5.41421356).abs() < 1e-9);
+
+        let err = parse_scenarios("version 1\n0 test.map 7 5 1 1 5 3\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::FieldCount {
+                expected: 9,
+                found: 8
+            }
+        );
+    }
+
+    #[test]
+    fn test_run_scenarios() {
+        let grid = parse_map(MAP).unwrap();
+        let scenarios = parse_scenarios(SCEN).unwrap();
+        let results = run_scenarios(
+            &grid,
+            &scenarios,
+            &[
+                AlgorithmType::Dijkstra,
+                AlgorithmType::AStar,
+                AlgorithmType::Jps,
+            ],
+        );
+
+        assert_eq!(results.len(), 6);
+        for result in &results {
+            assert!(result.is_optimal(), "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn test_run_scenarios_on_swamp_and_water() {
+        // Swamp costs the same as ground, water blocks the way below
+        let map = MAP.replace("@.....@\n@...T.@", "@SSSWW@\n@...T.@");
+        let grid = parse_map(&map).unwrap();
+        assert!(grid.has_uniform_costs());
+        assert_eq!(grid.get(4, 2), Some(Cell::Wall));
+
+        let scen = "version 1\n0\tt.map\t7\t5\t1\t1\t3\t2\t2.41421356\n";
+        let scenarios = parse_scenarios(scen).unwrap();
+        let results = run_scenarios(&grid, &scenarios, &[AlgorithmType::Jps]);
+        assert!(results[0].is_optimal(), "{:?}", results[0]);
+    }
+
+    #[test]
+    fn test_run_scenarios_errors() {
+        let grid = parse_map(MAP).unwrap();
+        let scen = "version 1
+0\ttest.map\t9\t9\t1\t1\t8\t8\t9.89949494
+0\ttest.map\t7\t5\t1\t1\t4\t3\t3.82842712
+0\ttest.map\t7\t5\t1\t1\t9\t3\t3.82842712
+";
+        let scenarios = parse_scenarios(scen).unwrap();
+        let results = run_scenarios(&grid, &scenarios, &[AlgorithmType::AStar]);
+        let errors: Vec<_> = results.iter().map(|r| r.length.unwrap_err()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                ScenarioError::MapSize((9, 9)),
+                ScenarioError::Path(PathError::EndIsWall),
+                ScenarioError::Path(PathError::OutOfBounds((9, 3))),
+            ]
+        );
+    }
+}
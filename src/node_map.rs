@@ -0,0 +1,200 @@
+use crate::grid::Grid;
+
+/// Per-cell search state stored in a flat array indexed by `y * width + x`,
+/// a faster stand-in for `HashMap<(usize, usize), T>` on grids.
+///
+/// Entries remember the generation they were written in, so `clear` only
+/// bumps the generation instead of touching every cell.
+#[derive(Clone, Debug)]
+pub struct NodeMap<T> {
+    width: usize,
+    height: usize,
+    generations: Vec<u32>,
+    values: Vec<T>,
+    generation: u32,
+    len: usize,
+}
+
+impl<T: Copy + Default> NodeMap<T> {
+    pub fn new(width: usize, height: usize) -> Self {
+        NodeMap {
+            width,
+            height,
+            generations: vec![0; width * height],
+            values: vec![T::default(); width * height],
+            generation: 1,
+            len: 0,
+        }
+    }
+
+    pub fn for_grid(grid: &Grid) -> Self {
+        Self::new(grid.width, grid.height)
+    }
+
+    fn index(&self, (x, y): (usize, usize)) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y * self.width + x)
+    }
+
+    pub fn get(&self, pos: (usize, usize)) -> Option<T> {
+        let idx = self.index(pos)?;
+        (self.generations[idx] == self.generation).then(|| self.values[idx])
+    }
+
+    pub fn contains(&self, pos: (usize, usize)) -> bool {
+        self.get(pos).is_some()
+    }
+
+    /// Returns the previous value. Positions outside the map are ignored
+    pub fn insert(&mut self, pos: (usize, usize), value: T) -> Option<T> {
+        let idx = self.index(pos)?;
+        let previous = self.get(pos);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        self.generations[idx] = self.generation;
+        self.values[idx] = value;
+        previous
+    }
+
+    pub fn remove(&mut self, pos: (usize, usize)) -> Option<T> {
+        let previous = self.get(pos)?;
+        let idx = self.index(pos)?;
+        self.generations[idx] = 0;
+        self.len -= 1;
+        Some(previous)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Empties the map in constant time. `SearchLog::restart` relies on it
+    /// to reset the node states each time D* Lite repairs its search
+    pub fn clear(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        if self.generation == 0 {
+            // Old generations would become current again after wrapping
+            self.generations.fill(0);
+            self.generation = 1;
+        }
+        self.len = 0;
+    }
+
+    /// Entries in row-major order
+    pub fn iter(&self) -> impl Iterator<Item = ((usize, usize), T)> + '_ {
+        self.generations
+            .iter()
+            .enumerate()
+            .filter(|&(_, &generation)| generation == self.generation)
+            .map(|(idx, _)| ((idx % self.width, idx / self.width), self.values[idx]))
+    }
+}
+
+/// Set of cells stored as one bit per cell, indexed like `NodeMap`
+#[derive(Clone, Debug)]
+pub struct NodeSet {
+    width: usize,
+    height: usize,
+    bits: Vec<u64>,
+    len: usize,
+}
+
+impl NodeSet {
+    pub fn new(width: usize, height: usize) -> Self {
+        NodeSet {
+            width,
+            height,
+            bits: vec![0; (width * height).div_ceil(64)],
+            len: 0,
+        }
+    }
+
+    pub fn for_grid(grid: &Grid) -> Self {
+        Self::new(grid.width, grid.height)
+    }
+
+    fn index(&self, (x, y): (usize, usize)) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y * self.width + x)
+    }
+
+    pub fn contains(&self, pos: (usize, usize)) -> bool {
+        self.index(pos)
+            .is_some_and(|idx| self.bits[idx / 64] & (1 << (idx % 64)) != 0)
+    }
+
+    /// Returns true if the position was not in the set yet
+    pub fn insert(&mut self, pos: (usize, usize)) -> bool {
+        let Some(idx) = self.index(pos) else {
+            return false;
+        };
+        let word = &mut self.bits[idx / 64];
+        let bit = 1 << (idx % 64);
+        let added = *word & bit == 0;
+        *word |= bit;
+        if added {
+            self.len += 1;
+        }
+        added
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_map() {
+        let mut map = NodeMap::new(4, 3);
+        assert_eq!(map.insert((3, 2), 7), None);
+        assert_eq!(map.insert((3, 2), 9), Some(7));
+        assert_eq!(map.insert((4, 0), 1), None);
+        assert_eq!(map.get((3, 2)), Some(9));
+        assert_eq!(map.get((4, 0)), None);
+        assert_eq!(map.len(), 1);
+
+        map.insert((1, 0), 5);
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![((1, 0), 5), ((3, 2), 9)]
+        );
+
+        assert_eq!(map.remove((1, 0)), Some(5));
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.get((3, 2)), None);
+        map.insert((0, 0), 2);
+        assert_eq!(map.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_node_set() {
+        let mut set = NodeSet::new(10, 10);
+        assert!(set.insert((9, 9)));
+        assert!(!set.insert((9, 9)));
+        assert!(set.insert((0, 7)));
+        assert!(!set.insert((10, 0)));
+        assert!(set.contains((9, 9)));
+        assert!(!set.contains((8, 9)));
+        assert_eq!(set.len(), 2);
+
+        set.clear();
+        assert!(!set.contains((9, 9)));
+    }
+}
@@ -1,24 +1,188 @@
 use crate::grid::Grid;
+use crate::search_space::{NodeStore, SearchSpace};
+use std::fmt;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
 pub enum NodeState {
+    #[default]
     Unvisited,
     InQueue,
     Visited,
+    /// Passed over by a scan without being queued (Jump Point Search)
+    Scanned,
+    /// InQueue and Visited of the search growing from the end (bidirectional)
+    BackwardInQueue,
+    BackwardVisited,
     Path,
 }
 
-/// Common interface for all pathfinding algorithms
-pub trait PathfindingAlgorithm {
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    /// Added to the frontier
+    Push,
+    /// Already in the frontier, reached again more cheaply
+    Relax,
+    /// Taken off the frontier and expanded
+    Pop,
+    /// Passed over by a scan (Jump Point Search)
+    Scan,
+    /// Part of the path found
+    PathMark,
+}
+
+/// One change to the state of a node, see `SearchLog`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SearchEvent<N = (usize, usize)> {
+    /// `SearchStats::steps` when the event happened, 0 before the first step
+    pub step: usize,
+    pub kind: EventKind,
+    pub position: N,
+    pub state: NodeState,
+    /// State of the node before the event, so the log can be replayed backwards
+    pub previous: NodeState,
+}
+
+/// Node states of a search along with the log of every change to them
+pub struct SearchLog<S: SearchSpace = Grid> {
+    states: S::Map<NodeState>,
+    events: Vec<SearchEvent<S::Node>>,
+}
+
+impl<S: SearchSpace> SearchLog<S> {
+    pub fn new(space: &S) -> Self {
+        SearchLog {
+            states: space.node_map(),
+            events: vec![],
+        }
+    }
+
+    pub fn record(&mut self, step: usize, kind: EventKind, position: S::Node, state: NodeState) {
+        let previous = self
+            .states
+            .insert(position, state)
+            .unwrap_or(NodeState::Unvisited);
+        self.events.push(SearchEvent {
+            step,
+            kind,
+            position,
+            state,
+            previous,
+        });
+    }
+
+    pub fn state(&self, position: S::Node) -> NodeState {
+        self.states.get(position).unwrap_or_default()
+    }
+
+    pub fn events(&self) -> &[SearchEvent<S::Node>] {
+        &self.events
+    }
+}
+
+impl SearchLog {
+    /// Drops the log and every state but `keep`, whose nodes are logged as
+    /// pushed at `step`. Used when a search is repaired instead of rerun
+    pub fn restart(&mut self, step: usize, keep: NodeState) {
+        let kept: Vec<(usize, usize)> = self
+            .states
+            .iter()
+            .filter(|&(_, state)| state == keep)
+            .map(|(position, _)| position)
+            .collect();
+
+        self.states.clear();
+        self.events.clear();
+        for position in kept {
+            self.record(step, EventKind::Push, position, keep);
+        }
+    }
+}
+
+/// Effort spent by a search so far
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SearchStats {
+    /// Nodes taken off the frontier and expanded
+    pub nodes_expanded: usize,
+    /// Entries added to the frontier, duplicates included
+    pub nodes_pushed: usize,
+    /// Largest number of entries the frontier held at once
+    pub max_frontier: usize,
+    /// Entries popped for a node that was already expanded or has since been
+    /// reached more cheaply. Most algorithms skip them, DFS expands them again
+    pub duplicate_pops: usize,
+    /// Calls to `step` that took something off the frontier
+    pub steps: usize,
+    /// `SearchSpace::path_cost` of the path, once one is found
+    pub path_cost: Option<u32>,
+}
+
+impl SearchStats {
+    /// Count a frontier push, `frontier` is the frontier size after it
+    pub fn record_push(&mut self, frontier: usize) {
+        self.nodes_pushed += 1;
+        self.max_frontier = self.max_frontier.max(frontier);
+    }
+}
+
+/// A path found by a finished search, see `PathfindingAlgorithm::run_to_end`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathResult<N = (usize, usize)> {
+    /// Cells from start to end, both included
+    pub path: Vec<N>,
+    /// `SearchSpace::path_cost` of the path
+    pub cost: u32,
+    pub stats: SearchStats,
+}
+
+/// Why no path could be returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathError {
+    /// The start or end, given here, lies outside the grid
+    OutOfBounds((usize, usize)),
+    StartIsWall,
+    EndIsWall,
+    /// The grid has more than one `Cell::Start`
+    MultipleStarts,
+    NoStart,
+    NoEnd,
+    /// The search finished without reaching the end
+    NoPath,
+    /// The named algorithm can't search the grid's neighborhood or terrain,
+    /// see `AlgorithmType::supports`
+    UnsupportedGrid(&'static str),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::OutOfBounds((x, y)) => write!(f, "({x}, {y}) is outside the grid"),
+            PathError::StartIsWall => write!(f, "start is inside a wall"),
+            PathError::EndIsWall => write!(f, "end is inside a wall"),
+            PathError::MultipleStarts => write!(f, "grid has more than one start"),
+            PathError::NoStart => write!(f, "grid has no start"),
+            PathError::NoEnd => write!(f, "grid has no end"),
+            PathError::NoPath => write!(f, "no path between start and end"),
+            PathError::UnsupportedGrid(name) => {
+                write!(f, "{name} can't search this grid's moves or terrain")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Common interface for all pathfinding algorithms. Most only search grids,
+/// `Dijkstra`, `AStar`, `Bfs` and `Dfs` search any `SearchSpace`
+pub trait PathfindingAlgorithm<S: SearchSpace = Grid> {
     /// Execute one step of the algorithm
     /// Returns true if still running, false if finished
-    fn step(&mut self, grid: &Grid) -> bool;
+    fn step(&mut self, space: &S) -> bool;
 
     /// Get the visual state of a node for rendering
-    fn get_node_state(&self, x: usize, y: usize) -> NodeState;
+    fn get_node_state(&self, node: S::Node) -> NodeState;
 
     /// Get the path from start to end (empty if no path found)
-    fn get_path(&self) -> Vec<(usize, usize)>;
+    fn get_path(&self) -> Vec<S::Node>;
 
     /// Check if the algorithm has finished executing
     fn is_finished(&self) -> bool;
@@ -28,4 +192,33 @@ pub trait PathfindingAlgorithm {
 
     /// Get the algorithm's display name for the UI
     fn name(&self) -> &'static str;
+
+    /// Counters describing the work done so far
+    fn stats(&self) -> SearchStats;
+
+    /// Every node state change so far, in order (see `Timeline`)
+    fn events(&self) -> &[SearchEvent<S::Node>];
+
+    /// Steps until the search finishes, returns None if it found no path
+    fn run_to_end(&mut self, space: &S) -> Option<PathResult<S::Node>> {
+        while self.step(space) {}
+        if !self.found_path() {
+            return None;
+        }
+
+        let path = self.get_path();
+        let stats = self.stats();
+        Some(PathResult {
+            cost: stats.path_cost.unwrap_or_else(|| space.path_cost(&path)),
+            path,
+            stats,
+        })
+    }
+
+    /// Notify the algorithm that the given cells changed (see `Grid::take_changes`)
+    /// Returns true if it repaired its state and can keep stepping, false if
+    /// the results are stale and the search has to be started over
+    fn notify_changes(&mut self, _space: &S, _changes: &[S::Node]) -> bool {
+        false
+    }
 }
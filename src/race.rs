@@ -0,0 +1,133 @@
+use crate::algorithms::{create_algorithm_to_nearest, AlgorithmType};
+use crate::astar::Heuristic;
+use crate::grid::Grid;
+use crate::pathfinding::PathfindingAlgorithm;
+
+/// One algorithm taking part in a `Race`, searching its own copy of the grid
+pub struct Racer {
+    pub algorithm_type: AlgorithmType,
+    pub algorithm: Box<dyn PathfindingAlgorithm>,
+    pub grid: Grid,
+    /// 1-based finishing place, racers finishing on the same step share it
+    pub place: Option<usize>,
+    /// Race step on which the racer finished
+    pub finished_at: Option<usize>,
+}
+
+/// Several algorithms searching copies of the same grid in lockstep, one
+/// `PathfindingAlgorithm::step` each per race step
+pub struct Race {
+    pub racers: Vec<Racer>,
+    steps: usize,
+}
+
+impl Race {
+    pub const MIN_RACERS: usize = 2;
+    pub const MAX_RACERS: usize = 4;
+
+    /// Algorithms that don't support the grid or come after `MAX_RACERS`
+    /// are left out of the race. Every racer stops at the first of `goals`
+    /// it reaches
+    pub fn new(
+        grid: &Grid,
+        lineup: &[AlgorithmType],
+        start: (usize, usize),
+        goals: &[(usize, usize)],
+        heuristic: &Heuristic,
+        weight: f32,
+    ) -> Self {
+        let racers = lineup
+            .iter()
+            .filter(|algorithm_type| algorithm_type.supports(grid))
+            .take(Self::MAX_RACERS)
+            .map(|&algorithm_type| Racer {
+                algorithm_type,
+                algorithm: create_algorithm_to_nearest(
+                    algorithm_type,
+                    grid,
+                    start,
+                    goals,
+                    heuristic,
+                    weight,
+                ),
+                grid: grid.clone(),
+                place: None,
+                finished_at: None,
+            })
+            .collect();
+
+        Race { racers, steps: 0 }
+    }
+
+    /// Steps every racer that is still running. Returns true while any is
+    pub fn step(&mut self) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+
+        self.steps += 1;
+        let place = 1 + self.racers.iter().filter(|r| r.place.is_some()).count();
+        for racer in self.racers.iter_mut().filter(|r| r.place.is_none()) {
+            if !racer.algorithm.step(&racer.grid) {
+                racer.place = Some(place);
+                racer.finished_at = Some(self.steps);
+            }
+        }
+
+        !self.is_finished()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.racers.iter().all(|r| r.place.is_some())
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Finished racers in finishing order
+    pub fn standings(&self) -> Vec<&Racer> {
+        let mut finished: Vec<&Racer> = self.racers.iter().filter(|r| r.place.is_some()).collect();
+        finished.sort_by_key(|r| r.place);
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Cell;
+
+    #[test]
+    fn test_finish_order() {
+        let mut grid = Grid::new(10, 10);
+        for y in 0..9 {
+            grid.set(5, y, Cell::Wall);
+        }
+        let lineup = [
+            AlgorithmType::Dijkstra,
+            AlgorithmType::AStar,
+            AlgorithmType::Bfs,
+        ];
+        let mut race = Race::new(
+            &grid,
+            &lineup,
+            (0, 0),
+            &[(9, 0)],
+            &Heuristic::Manhattan,
+            1.0,
+        );
+
+        while race.step() {}
+
+        assert!(race.is_finished());
+        let standings = race.standings();
+        assert_eq!(standings[0].algorithm_type, AlgorithmType::AStar);
+        assert_eq!(standings[0].place, Some(1));
+        // Dijkstra and BFS expand the same cells on uniform terrain
+        assert_eq!(standings[1].place, Some(2));
+        assert_eq!(standings[2].place, Some(2));
+        assert_eq!(race.steps(), standings[2].finished_at.unwrap());
+        assert!(race.racers.iter().all(|r| r.algorithm.found_path()));
+    }
+}
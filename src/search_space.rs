@@ -0,0 +1,185 @@
+use crate::node_map::{NodeMap, NodeSet};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// Per-node storage used by a search, see `SearchSpace::Map`
+pub trait NodeStore<N, T> {
+    fn get(&self, node: N) -> Option<T>;
+
+    /// Returns the previous value
+    fn insert(&mut self, node: N, value: T) -> Option<T>;
+
+    fn contains(&self, node: N) -> bool {
+        self.get(node).is_some()
+    }
+}
+
+impl<T: Copy + Default> NodeStore<(usize, usize), T> for NodeMap<T> {
+    fn get(&self, node: (usize, usize)) -> Option<T> {
+        NodeMap::get(self, node)
+    }
+
+    fn insert(&mut self, node: (usize, usize), value: T) -> Option<T> {
+        NodeMap::insert(self, node, value)
+    }
+}
+
+/// The storage for spaces without a dense numbering of their nodes
+impl<N: Eq + Hash, T: Copy> NodeStore<N, T> for HashMap<N, T> {
+    fn get(&self, node: N) -> Option<T> {
+        HashMap::get(self, &node).copied()
+    }
+
+    fn insert(&mut self, node: N, value: T) -> Option<T> {
+        HashMap::insert(self, node, value)
+    }
+}
+
+/// Set of nodes used by a search, see `SearchSpace::Set`
+pub trait NodeSetStore<N> {
+    fn contains(&self, node: N) -> bool;
+
+    /// Returns true if the node was not in the set yet
+    fn insert(&mut self, node: N) -> bool;
+}
+
+impl NodeSetStore<(usize, usize)> for NodeSet {
+    fn contains(&self, node: (usize, usize)) -> bool {
+        NodeSet::contains(self, node)
+    }
+
+    fn insert(&mut self, node: (usize, usize)) -> bool {
+        NodeSet::insert(self, node)
+    }
+}
+
+impl<N: Eq + Hash> NodeSetStore<N> for HashSet<N> {
+    fn contains(&self, node: N) -> bool {
+        HashSet::contains(self, &node)
+    }
+
+    fn insert(&mut self, node: N) -> bool {
+        HashSet::insert(self, node)
+    }
+}
+
+/// A graph the generic algorithms (`Dijkstra`, `AStar`, `Bfs`, `Dfs`) can
+/// search, such as a `Grid`, a road network or a navmesh
+pub trait SearchSpace {
+    type Node: Copy + Eq + Default + Debug;
+
+    /// Storage for per-node search state, `NodeMap` on grids
+    type Map<T: Copy + Default>: NodeStore<Self::Node, T>;
+
+    /// Set of nodes such as the expanded ones, a `NodeSet` bitset on grids
+    type Set: NodeSetStore<Self::Node>;
+
+    /// Create empty per-node storage large enough for every node
+    fn node_map<T: Copy + Default>(&self) -> Self::Map<T>;
+
+    /// Create an empty set that can hold every node
+    fn node_set(&self) -> Self::Set;
+
+    /// Nodes reachable from `node` in one move, along with the cost of the move
+    fn successors(&self, node: Self::Node) -> impl Iterator<Item = (Self::Node, u32)> + '_;
+
+    /// Estimate of the cost from `from` to `to` that guides A*. It has to
+    /// stay at or below the real cost for A* to find the cheapest path, the
+    /// default of 0 makes A* expand nodes like Dijkstra
+    fn heuristic(&self, _from: Self::Node, _to: Self::Node) -> u32 {
+        0
+    }
+
+    /// Move costs summed along a path, moves that aren't successors count as 0
+    fn path_cost(&self, path: &[Self::Node]) -> u32 {
+        path.windows(2)
+            .filter_map(|pair| {
+                self.successors(pair[0])
+                    .find(|&(node, _)| node == pair[1])
+                    .map(|(_, cost)| cost)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::astar::AStar;
+    use crate::bfs::Bfs;
+    use crate::dfs::Dfs;
+    use crate::dijkstra::Dijkstra;
+    use crate::pathfinding::PathfindingAlgorithm;
+
+    /// Directed graph of numbered junctions with a straight-line distance
+    /// to every other junction for the heuristic
+    struct Roads {
+        edges: Vec<Vec<(usize, u32)>>,
+        positions: Vec<(u32, u32)>,
+    }
+
+    impl SearchSpace for Roads {
+        type Node = usize;
+        type Map<T: Copy + Default> = HashMap<usize, T>;
+        type Set = HashSet<usize>;
+
+        fn node_map<T: Copy + Default>(&self) -> HashMap<usize, T> {
+            HashMap::new()
+        }
+
+        fn node_set(&self) -> HashSet<usize> {
+            HashSet::new()
+        }
+
+        fn successors(&self, node: usize) -> impl Iterator<Item = (usize, u32)> + '_ {
+            self.edges[node].iter().copied()
+        }
+
+        fn heuristic(&self, from: usize, to: usize) -> u32 {
+            let (a, b) = (self.positions[from], self.positions[to]);
+            a.0.abs_diff(b.0).max(a.1.abs_diff(b.1))
+        }
+    }
+
+    fn roads() -> Roads {
+        // 0 -> 1 -> 3 is shorter than the direct road 0 -> 3, 4 is unreachable
+        Roads {
+            edges: vec![
+                vec![(1, 4), (2, 3), (3, 10)],
+                vec![(3, 4)],
+                vec![(3, 7)],
+                vec![],
+                vec![(0, 1)],
+            ],
+            positions: vec![(0, 0), (4, 0), (0, 3), (6, 2), (9, 9)],
+        }
+    }
+
+    #[test]
+    fn test_algorithms_on_custom_space() {
+        let roads = roads();
+        let mut algorithms: Vec<Box<dyn PathfindingAlgorithm<Roads>>> = vec![
+            Box::new(Dijkstra::new(&roads, 0, 3)),
+            Box::new(AStar::for_space(&roads, 0, 3)),
+            Box::new(Bfs::new(&roads, 0, 3)),
+            Box::new(Dfs::new(&roads, 0, 3)),
+        ];
+        for algorithm in &mut algorithms {
+            let result = algorithm.run_to_end(&roads).unwrap();
+            assert_eq!(result.path.first(), Some(&0), "{}", algorithm.name());
+            assert_eq!(result.path.last(), Some(&3), "{}", algorithm.name());
+            assert_eq!(result.cost, roads.path_cost(&result.path));
+        }
+
+        let mut dijkstra = Dijkstra::new(&roads, 0, 3);
+        assert_eq!(dijkstra.run_to_end(&roads).unwrap().path, vec![0, 1, 3]);
+        let mut astar = AStar::for_space(&roads, 0, 3);
+        assert_eq!(astar.run_to_end(&roads).unwrap().cost, 8);
+
+        let mut bfs = Bfs::new(&roads, 4, 3);
+        assert_eq!(bfs.run_to_end(&roads).map(|r| r.path.len()), Some(3));
+        let mut unreachable = Dijkstra::new(&roads, 3, 4);
+        assert_eq!(unreachable.run_to_end(&roads), None);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::pathfinding::{NodeState, SearchEvent};
+use std::collections::HashMap;
+
+/// Replays the event log of a search (`PathfindingAlgorithm::events`) to show
+/// its node states at any step it went through, without running it again
+pub struct Timeline {
+    events: Vec<SearchEvent>,
+    states: HashMap<(usize, usize), NodeState>,
+    /// Number of events applied to `states`
+    applied: usize,
+    step: usize,
+}
+
+impl Timeline {
+    /// Starts at the last step of the log
+    pub fn new(events: &[SearchEvent]) -> Self {
+        let mut timeline = Timeline {
+            events: events.to_vec(),
+            states: HashMap::new(),
+            applied: 0,
+            step: 0,
+        };
+        timeline.seek(timeline.last_step());
+        timeline
+    }
+
+    pub fn first_step(&self) -> usize {
+        self.events.first().map_or(0, |e| e.step)
+    }
+
+    pub fn last_step(&self) -> usize {
+        self.events.last().map_or(0, |e| e.step)
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Shows the states as they were right after `step`, clamped to the log
+    pub fn seek(&mut self, step: usize) {
+        let step = step.clamp(self.first_step(), self.last_step());
+
+        while let Some(event) = self.events.get(self.applied) {
+            if event.step > step {
+                break;
+            }
+            self.states.insert(event.position, event.state);
+            self.applied += 1;
+        }
+
+        while self.applied > 0 {
+            let event = &self.events[self.applied - 1];
+            if event.step <= step {
+                break;
+            }
+            self.states.insert(event.position, event.previous);
+            self.applied -= 1;
+        }
+
+        self.step = step;
+    }
+
+    pub fn step_forward(&mut self) {
+        self.seek(self.step + 1);
+    }
+
+    pub fn step_back(&mut self) {
+        self.seek(self.step.saturating_sub(1));
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.step == self.last_step()
+    }
+
+    pub fn get_node_state(&self, pos: (usize, usize)) -> NodeState {
+        *self.states.get(&pos).unwrap_or(&NodeState::Unvisited)
+    }
+
+    /// What happened during the current step
+    pub fn current_events(&self) -> &[SearchEvent] {
+        let start = self.events[..self.applied]
+            .iter()
+            .rposition(|e| e.step != self.step)
+            .map_or(0, |i| i + 1);
+        &self.events[start..self.applied]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::astar::{AStar, Heuristic};
+    use crate::grid::{Cell, Grid};
+    use crate::pathfinding::{EventKind, PathfindingAlgorithm};
+
+    fn snapshot(grid: &Grid, state: impl Fn(usize, usize) -> NodeState) -> Vec<NodeState> {
+        (0..grid.height)
+            .flat_map(|y| (0..grid.width).map(move |x| (x, y)))
+            .map(|(x, y)| state(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn test_replays_every_step() {
+        let mut grid = Grid::new(8, 6);
+        for y in 1..6 {
+            grid.set(4, y, Cell::Wall);
+        }
+        let mut astar = AStar::new(&grid, (1, 4), (6, 4), Heuristic::Manhattan);
+
+        let mut snapshots = vec![snapshot(&grid, |x, y| astar.get_node_state((x, y)))];
+        while astar.step(&grid) {
+            snapshots.push(snapshot(&grid, |x, y| astar.get_node_state((x, y))));
+        }
+        snapshots.push(snapshot(&grid, |x, y| astar.get_node_state((x, y))));
+
+        let mut timeline = Timeline::new(astar.events());
+        assert_eq!(timeline.last_step(), astar.stats().steps);
+        assert_eq!(
+            snapshot(&grid, |x, y| timeline.get_node_state((x, y))),
+            snapshots[snapshots.len() - 1]
+        );
+
+        // Backwards one step at a time, then jump around
+        for step in (0..timeline.last_step()).rev() {
+            timeline.step_back();
+            assert_eq!(timeline.step(), step);
+            assert_eq!(
+                snapshot(&grid, |x, y| timeline.get_node_state((x, y))),
+                snapshots[step]
+            );
+        }
+        for step in [5, 2, 9, 0, 7] {
+            timeline.seek(step);
+            assert_eq!(
+                snapshot(&grid, |x, y| timeline.get_node_state((x, y))),
+                snapshots[step]
+            );
+        }
+    }
+
+    #[test]
+    fn test_current_events() {
+        let grid = Grid::new(3, 1);
+        let mut astar = AStar::new(&grid, (0, 0), (2, 0), Heuristic::Manhattan);
+        while astar.step(&grid) {}
+
+        let mut timeline = Timeline::new(astar.events());
+        timeline.seek(1);
+        let kinds: Vec<EventKind> = timeline.current_events().iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, vec![EventKind::Pop, EventKind::Push]);
+        assert_eq!(timeline.current_events()[1].position, (1, 0));
+    }
+}